@@ -1,9 +1,169 @@
 use graph::Graph;
+use lexer::Span;
 use std::path::posix::Path;
 
+pub use self::EdgeKind::{Inheritance, Implementation, Contains, Constraint, Field, Parameter, Instantiation, StaticAccess,
+                         Reference};
+pub use self::TypeKind::{Class, Struct, Interface, Enum, Record, RecordStruct, Delegate, Namespace, Cycle};
+
 pub struct GraphInfo {
     pub name: String,
-    pub graph: Graph<String, ()>,
+    pub graph: Graph<TypeNode, EdgeInfo>,
+    /// Markers used on nodes, each with the name of the type it stands for,
+    /// for types listed apart from the graph.
+    pub legend: Vec<(String, String)>,
+}
+
+/// A place in the source code.
+#[derive(Clone, PartialEq, Show)]
+pub struct Location {
+    pub file: Path,
+    pub span: Span,
+}
+
+/// The data carried by each node: a declared type.
+#[derive(Clone, Show)]
+pub struct TypeNode {
+    /// Fully qualified name of the type.
+    pub name: String,
+    pub kind: TypeKind,
+    /// The namespace the type is declared in, empty for the global one.
+    pub namespace: String,
+    /// The project the type belongs to, if it is known.
+    pub project: Option<String>,
+    /// Where the type is declared, several places for a partial type.
+    pub locations: Vec<Location>,
+    /// Markers for the types this one refers to whose edges aren't drawn.
+    pub markers: Vec<String>,
+}
+
+/// The data carried by each edge: the relationship, and where it is first
+/// found in the source type.
+#[derive(Clone, Show)]
+pub struct EdgeInfo {
+    pub kind: EdgeKind,
+    pub location: Location,
+    /// How many edges this one stands for, more than one where nodes were
+    /// merged.
+    pub weight: usize,
+    /// The edge is implied by a longer path, and is only drawn faintly.
+    pub implied: bool,
+}
+
+/// The kind of declaration a type comes from.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
+pub enum TypeKind {
+    Class,
+    Struct,
+    Interface,
+    Enum,
+    Record,
+    RecordStruct,
+    Delegate,
+    /// Not a type, but a whole namespace collapsed into a single node.
+    Namespace,
+    /// Not a type, but the types of a dependency cycle collapsed into a
+    /// single node.
+    Cycle,
+}
+
+impl TypeKind {
+    /// The name used for the kind in outputs.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Class => "class",
+            Struct => "struct",
+            Interface => "interface",
+            Enum => "enum",
+            Record => "record",
+            RecordStruct => "record struct",
+            Delegate => "delegate",
+            Namespace => "namespace",
+            Cycle => "cycle",
+        }
+    }
+}
+
+/// The relationship an edge stands for. Kinds are ordered from strongest to
+/// weakest, so when one type refers to another in several ways the smallest
+/// kind is the one kept.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
+pub enum EdgeKind {
+    /// The source derives from the target.
+    Inheritance,
+    /// The source implements the target interface. An interface extending
+    /// another counts as inheritance.
+    Implementation,
+    /// The target is a type nested inside the source.
+    Contains,
+    /// The target constrains a type parameter of the source, as in
+    /// `where T : Target`.
+    Constraint,
+    /// A field or property of the source has the target type.
+    Field,
+    /// The target appears in the signature of a method of the source.
+    Parameter,
+    /// The source creates instances of the target with `new`.
+    Instantiation,
+    /// The source accesses a static member of the target.
+    StaticAccess,
+    /// Any other mention of the target within the source.
+    Reference,
+}
+
+impl EdgeKind {
+    pub fn all() -> Vec<EdgeKind> {
+        vec![Inheritance, Implementation, Contains, Constraint, Field, Parameter, Instantiation, StaticAccess, Reference]
+    }
+
+    /// The name used for the kind on the command line and in outputs.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Inheritance => "inheritance",
+            Implementation => "implementation",
+            Contains => "contains",
+            Constraint => "constraint",
+            Field => "field",
+            Parameter => "parameter",
+            Instantiation => "instantiation",
+            StaticAccess => "static",
+            Reference => "reference",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EdgeKind> {
+        EdgeKind::all().into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+impl EdgeInfo {
+    /// Fold another edge between the same two nodes into this one, keeping
+    /// the strongest kind and the first location.
+    pub fn merge(&mut self, other: EdgeInfo) {
+        self.weight += other.weight;
+        if other.kind < self.kind {
+            self.kind = other.kind;
+        }
+        self.implied = self.implied && other.implied;
+    }
+}
+
+impl GraphInfo {
+    /// Copy of the graph keeping only the edges of the given kinds.
+    pub fn with_edge_kinds(&self, kinds: &[EdgeKind]) -> GraphInfo {
+        let mut graph = Graph::new();
+        self.graph.each_node(|_, node| {
+            graph.add_node(node.data.clone());
+            true
+        });
+        self.graph.each_edge(|_, edge| {
+            if kinds.contains(&edge.data.kind) {
+                graph.add_edge(edge.source(), edge.target(), edge.data.clone());
+            }
+            true
+        });
+        GraphInfo { name: self.name.clone(), graph: graph, legend: self.legend.clone() }
+    }
 }
 
 pub trait LanguageBackend {
@@ -0,0 +1,225 @@
+//! Collapsing groups of types into single nodes, so that a large solution
+//! can be looked at one namespace at a time, or as layers without the cycles
+//! between types. The edges between two nodes are merged into one counting
+//! them.
+
+use std::collections::HashMap;
+use graph::IndexedGraph;
+use backend::{GraphInfo, TypeNode};
+use backend::TypeKind::{Namespace, Cycle};
+
+/// How many types of a collapsed cycle are listed in its name.
+const LISTED_MEMBERS: usize = 5;
+
+/// Copy of the graph with the types in each of the given namespaces, or in
+/// namespaces inside them, merged into a single node.
+pub fn collapse_namespaces(ginfo: &GraphInfo, namespaces: &[String]) -> GraphInfo {
+    let groups: Vec<Option<String>> = ginfo.graph.all_nodes().iter()
+        .map(|node| collapsed_into(&node.data.namespace[], namespaces).map(|namespace| namespace.to_string()))
+        .collect();
+
+    // The project is kept when all the types in the namespace share it.
+    let mut projects: HashMap<String, Option<String>> = HashMap::new();
+    for (node, group) in ginfo.graph.all_nodes().iter().zip(groups.iter()) {
+        if let Some(ref namespace) = *group {
+            let project = match projects.get(namespace) {
+                Some(project) if *project != node.data.project => None,
+                Some(project) => project.clone(),
+                None => node.data.project.clone(),
+            };
+            projects.insert(namespace.clone(), project);
+        }
+    }
+
+    merge(ginfo, &groups[], |namespace, _| TypeNode {
+        name: namespace.to_string(),
+        kind: Namespace,
+        namespace: parent(namespace).to_string(),
+        project: projects.get(namespace).unwrap().clone(),
+        locations: vec![],
+        markers: vec![],
+    })
+}
+
+/// Copy of the graph with the types of each strongly connected component
+/// merged into a single node, leaving a graph without cycles. The node is
+/// named after the types in it.
+pub fn collapse_cycles(ginfo: &GraphInfo) -> GraphInfo {
+    let g = &ginfo.graph;
+    let mut groups: Vec<Option<String>> = g.all_nodes().iter().map(|_| None).collect();
+    let mut merged = HashMap::new();
+    for component in g.strongly_connected_components().into_iter().filter(|c| c.len() > 1) {
+        let key = format!("cycle {}", merged.len());
+        let nodes: Vec<&TypeNode> = component.iter().map(|&n| g.node_data(n)).collect();
+
+        let mut lines: Vec<String> = nodes.iter().take(LISTED_MEMBERS).map(|node| node.name.clone()).collect();
+        if nodes.len() > LISTED_MEMBERS {
+            lines.push(format!("and {} more", nodes.len() - LISTED_MEMBERS));
+        }
+        // The namespace and project are kept when all the types share them.
+        let first = nodes[0];
+        let namespace = if nodes.iter().all(|node| node.namespace == first.namespace) {
+            first.namespace.clone()
+        } else {
+            String::new()
+        };
+        let project = if nodes.iter().all(|node| node.project == first.project) { first.project.clone() } else { None };
+
+        merged.insert(key.clone(), TypeNode {
+            name: lines.connect("\n"),
+            kind: Cycle,
+            namespace: namespace,
+            project: project,
+            locations: vec![],
+            markers: vec![],
+        });
+        for node in component.iter() {
+            groups[node.node_id()] = Some(key.clone());
+        }
+    }
+    merge(ginfo, &groups[], |key, _| merged.get(key).unwrap().clone())
+}
+
+/// Copy of the graph with each node that has a group key merged into a
+/// single node for the group, made by `make` from the key and the first
+/// type in the group.
+fn merge<F>(ginfo: &GraphInfo, groups: &[Option<String>], mut make: F) -> GraphInfo where
+    F: FnMut(&str, &TypeNode) -> TypeNode,
+{
+    // Groups are keyed by their keys, other nodes aren't keyed at all.
+    let mut graph = IndexedGraph::new();
+    // Where each node ends up in the new graph.
+    let mut indices = vec![];
+
+    for (node, group) in ginfo.graph.all_nodes().iter().zip(groups.iter()) {
+        let node = &node.data;
+        let key = match *group {
+            Some(ref key) => key,
+            None => {
+                indices.push(graph.add_node(node.clone()));
+                continue;
+            },
+        };
+
+        let idx = graph.get_or_add_node(&key[], || make(&key[], node));
+        let merged = graph.mut_node_data(idx);
+        merged.locations.push_all(&node.locations[]);
+        for marker in node.markers.iter() {
+            if !merged.markers.contains(marker) {
+                merged.markers.push(marker.clone());
+            }
+        }
+        indices.push(idx);
+    }
+
+    for edge in ginfo.graph.all_edges().iter() {
+        let source = indices[edge.source().node_id()];
+        let target = indices[edge.target().node_id()];
+        if source == target {
+            // Within a group.
+            continue;
+        }
+        graph.add_or_update_edge(source, target, edge.data.clone(), |info, other| info.merge(other));
+    }
+
+    GraphInfo { name: ginfo.name.clone(), graph: graph.into_graph(), legend: ginfo.legend.clone() }
+}
+
+/// The outermost of the namespaces that is or contains the given one.
+fn collapsed_into<'a>(namespace: &str, namespaces: &'a [String]) -> Option<&'a str> {
+    let mut outermost: Option<&'a str> = None;
+    for candidate in namespaces.iter() {
+        let inside = namespace == &candidate[] ||
+            (namespace.starts_with(&candidate[]) && namespace[candidate.len()..].starts_with("."));
+        if inside && outermost.map_or(true, |o| candidate.len() < o.len()) {
+            outermost = Some(&candidate[]);
+        }
+    }
+    outermost
+}
+
+/// The namespace containing the given one, e.g. `A.B` for `A.B.C`.
+fn parent(namespace: &str) -> &str {
+    match namespace.rfind('.') {
+        Some(i) => &namespace[..i],
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::NodeIndex;
+    use backend::{GraphInfo, EdgeKind, TypeKind};
+    use test_support::test_graph;
+    use backend::TypeKind::{Class, Namespace, Cycle};
+    use backend::EdgeKind::{Inheritance, Field, Reference};
+
+    fn create_graph(extra: &[(usize, usize, EdgeKind)]) -> GraphInfo {
+        let mut edges = vec![(0, 1, Field), (0, 3, Reference), (0, 3, Field), (1, 2, Field), (1, 3, Reference),
+                             (2, 4, Reference), (3, 4, Inheritance)];
+        edges.push_all(extra);
+        test_graph(&["App.Main", "App.Data.Repo", "App.Data.Sql.Conn", "App.Data.Row", "Lib.Util"], &edges[])
+    }
+
+    #[test]
+    fn collapse() {
+        let namespaces = vec!["App.Data".to_string(), "App.Data.Sql".to_string()];
+        let ginfo = collapse_namespaces(&create_graph(&[]), &namespaces[]);
+        let g = &ginfo.graph;
+
+        let nodes: Vec<(String, String)> = g.all_nodes().iter()
+            .map(|node| (node.data.name.clone(), node.data.namespace.clone()))
+            .collect();
+        assert_eq!(nodes, vec![("App.Main".to_string(), "App".to_string()), ("App.Data".to_string(), "App".to_string()),
+                               ("Lib.Util".to_string(), "Lib".to_string())]);
+        assert_eq!(g.node_data(NodeIndex(1)).kind, Namespace);
+
+        let edges: Vec<(String, String, EdgeKind, usize)> = g.all_edges().iter()
+            .map(|edge| (g.node_data(edge.source()).name.clone(), g.node_data(edge.target()).name.clone(),
+                         edge.data.kind, edge.data.weight))
+            .collect();
+        assert_eq!(edges, vec![
+            ("App.Main".to_string(), "App.Data".to_string(), Field, 3),
+            ("App.Data".to_string(), "Lib.Util".to_string(), Inheritance, 2),
+        ]);
+    }
+
+    #[test]
+    fn collapse_projects() {
+        let mut ginfo = create_graph(&[]);
+        for i in range(0, 5) {
+            ginfo.graph.mut_node_data(NodeIndex(i)).project = Some("App".to_string());
+        }
+        ginfo.graph.mut_node_data(NodeIndex(3)).project = Some("Data".to_string());
+
+        let namespaces = vec!["App.Data.Sql".to_string(), "App.Data".to_string()];
+        let ginfo = collapse_namespaces(&ginfo, &namespaces[1..]);
+        assert_eq!(ginfo.graph.node_data(NodeIndex(1)).project, None);
+        assert_eq!(ginfo.graph.node_data(NodeIndex(0)).project, Some("App".to_string()));
+
+        let mut ginfo = create_graph(&[]);
+        ginfo.graph.mut_node_data(NodeIndex(2)).project = Some("Sql".to_string());
+        let ginfo = collapse_namespaces(&ginfo, &namespaces[..1]);
+        assert_eq!(ginfo.graph.node_data(NodeIndex(2)).project, Some("Sql".to_string()));
+    }
+
+    #[test]
+    fn cycles() {
+        // Repo and Row refer to each other, as do Conn and Util.
+        let ginfo = collapse_cycles(&create_graph(&[(3, 1, Reference), (4, 2, Reference)]));
+        let g = &ginfo.graph;
+
+        let nodes: Vec<(String, String, TypeKind)> = g.all_nodes().iter()
+            .map(|node| (node.data.name.clone(), node.data.namespace.clone(), node.data.kind))
+            .collect();
+        assert_eq!(nodes, vec![("App.Main".to_string(), "App".to_string(), Class),
+                               ("App.Data.Repo\nApp.Data.Row".to_string(), "App.Data".to_string(), Cycle),
+                               ("App.Data.Sql.Conn\nLib.Util".to_string(), "".to_string(), Cycle)]);
+
+        let edges: Vec<(usize, usize, EdgeKind, usize)> = g.all_edges().iter()
+            .map(|edge| (edge.source().node_id(), edge.target().node_id(), edge.data.kind, edge.data.weight))
+            .collect();
+        assert_eq!(edges, vec![(0, 1, Field, 3), (1, 2, Inheritance, 2)]);
+    }
+}
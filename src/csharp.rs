@@ -1,16 +1,28 @@
 use std::path::posix::Path;
 use std::io::File;
+use std::io::stdio::stderr;
+use std::ascii::AsciiExt;
 use regex::Regex;
-use std::collections::{HashSet, HashMap};
+use std::collections::{HashMap, HashSet};
+use std::mem::replace;
 
-use graph::Graph;
-use self::TokenClass::{Whitespace, Newline, Comment, StringLiteral, BlockBegin, BlockEnd, IdentifierOrKeyword};
+use graph::{Graph, IndexedGraph, NodeIndex};
+use self::TokenClass::{Whitespace, Newline, Comment, Directive, StringLiteral, BlockBegin, BlockEnd, ParenBegin, ParenEnd,
+                       BracketBegin, BracketEnd, LessThan, GreaterThan, Dot, Comma, Colon, Semicolon, Equals,
+                       Keyword, Identifier};
+use self::UsingDirective::{Import, Alias};
 use lexer::Lexer;
 use lexer::TokenIterator;
 use lexer::Token::{Matched, Unmatched};
 use lexer::Token;
+use lexer::Span;
 use backend::LanguageBackend;
 use backend::GraphInfo;
+use backend::{TypeNode, TypeKind, EdgeInfo, Location};
+use backend::TypeKind::{Class, Struct, Interface, Enum, Record, RecordStruct, Delegate};
+use backend::EdgeKind;
+use backend::EdgeKind::{Inheritance, Implementation, Contains, Constraint, Field, Parameter, Instantiation,
+                        StaticAccess, Reference};
 
 
 macro_rules! unwrap_or_return {
@@ -25,49 +37,429 @@ macro_rules! unwrap_or_return {
 }
 
 
-pub struct Csharp;
+pub struct Csharp {
+    /// Drop references to well known types of the base class library, so
+    /// that a type of the same name declared in the code doesn't pick up an
+    /// edge from everything using, say, `Task`.
+    pub ignore_bcl: bool,
+    /// Preprocessor symbols to consider defined, in addition to those
+    /// defined by the `DefineConstants` of the project each file belongs to.
+    pub defines: Vec<String>,
+    /// The configuration, such as `Debug`, whose `DefineConstants` are used.
+    pub configuration: String,
+}
+
+/// Reserved keywords, which can never be identifiers.
+static KEYWORDS: &'static [&'static str] = &[
+    "abstract", "as", "base", "bool", "break", "byte", "case", "catch", "char", "checked", "class", "const",
+    "continue", "decimal", "default", "delegate", "do", "double", "else", "enum", "event", "explicit", "extern",
+    "false", "finally", "fixed", "float", "for", "foreach", "goto", "if", "implicit", "in", "int", "interface",
+    "internal", "is", "lock", "long", "namespace", "new", "null", "object", "operator", "out", "override",
+    "params", "private", "protected", "public", "readonly", "ref", "return", "sbyte", "sealed", "short",
+    "sizeof", "stackalloc", "static", "string", "struct", "switch", "this", "throw", "true", "try", "typeof",
+    "uint", "ulong", "unchecked", "unsafe", "ushort", "using", "virtual", "void", "volatile", "while",
+];
+
+/// Contextual keywords, which are only keywords in certain places and lex as
+/// identifiers. They're never taken to be references to types.
+static CONTEXTUAL_KEYWORDS: &'static [&'static str] = &[
+    "add", "alias", "and", "ascending", "async", "await", "by", "descending", "dynamic", "equals",
+    "file", "from", "get", "global", "group", "init", "into", "join", "let", "managed", "nameof", "nint", "not",
+    "notnull", "nuint", "on", "or", "orderby", "partial", "record", "remove", "required", "scoped", "select",
+    "set", "unmanaged", "value", "var", "when", "where", "with", "yield",
+];
+
+/// Names of commonly used types from the base class library.
+static BCL_TYPES: &'static [&'static str] = &[
+    "Action", "Activator", "ArgumentException", "ArgumentNullException", "ArgumentOutOfRangeException", "Array",
+    "Attribute", "Boolean", "Byte", "CancellationToken", "Char", "Console", "Convert", "DateTime",
+    "DateTimeOffset", "Decimal", "Dictionary", "Double", "Encoding", "Enum", "Enumerable", "Environment",
+    "EventArgs", "EventHandler", "Exception", "File", "Func", "GC", "Guid", "HashSet", "ICollection",
+    "IComparable", "IDictionary", "IDisposable", "IEnumerable", "IEnumerator", "IEquatable", "IList",
+    "IQueryable", "IReadOnlyCollection", "IReadOnlyDictionary", "IReadOnlyList", "Int16", "Int32", "Int64",
+    "InvalidOperationException", "KeyValuePair", "Lazy", "List", "Math", "Memory", "NotImplementedException",
+    "NotSupportedException", "Nullable", "Object", "Path", "Predicate", "Queue", "Random", "Regex", "Single",
+    "Span", "Stack", "Stream", "String", "StringBuilder", "Task", "Thread", "TimeSpan", "Tuple", "Type", "Uri",
+    "ValueTask", "ValueTuple",
+];
 
 impl LanguageBackend for Csharp {
     fn get_extensions(&self) -> Vec<String> {
-        vec!["cs".to_string()]
+        vec!["cs".to_string(), "csproj".to_string()]
     }
 
-    /// Build a graph where classes constitute the nodes, and edges are
+    /// Build a graph where types constitute the nodes, and edges are
     /// formed by references between them.
     fn build_graphs(&self, paths: &[Path]) -> Vec<GraphInfo> {
         // We're going to assume the C# file has valid syntax and pull out the
         // exact information we need.
-        
-        let mut g = Graph::new();
-        
-        let map = build_map(paths);
 
-        let mut nodes = HashMap::new();
-        for classname in map.keys() {
-            nodes.insert(classname, g.add_node(classname.to_string()));
+        let mut projects = vec![];
+        let mut sources = vec![];
+        for path in paths.iter() {
+            if path.extension_str() == Some("csproj") {
+                projects.push(path.clone());
+            } else {
+                sources.push(path.clone());
+            }
         }
 
-        for nameA in map.keys() {
-            for (nameB, set) in map.iter() {
-                if set.contains(nameA) {
-                    g.add_edge(*nodes.get(nameB).unwrap(), *nodes.get(nameA).unwrap(), ());
-                }
-            }
+        // Each project's symbols only apply to its own files.
+        let global: HashSet<String> = self.defines.iter().map(|d| d.clone()).collect();
+        let defines: Vec<HashSet<String>> = projects.iter()
+            .map(|project| {
+                let mut defines = global.clone();
+                defines.extend(project_defines(project, &self.configuration[]).into_iter());
+                defines
+            })
+            .collect();
+        let files: Vec<(&Path, &HashSet<String>)> = sources.iter()
+            .map(|file| (file, closest_project(file, &projects[]).map_or(&global, |i| &defines[i])))
+            .collect();
+
+        let map = build_map(&files[]);
+        let mut graph = link(&map, self.ignore_bcl);
+        for i in range(0, graph.all_nodes().len()) {
+            let node = graph.mut_node_data(NodeIndex(i));
+            node.project = project_of(&node.locations[0].file, &projects[]);
         }
 
         let mut vec = Vec::new();
-        vec.push(GraphInfo { name: "reference_graph".to_string(), graph: g});
+        vec.push(GraphInfo { name: "reference_graph".to_string(), graph: graph, legend: vec![] });
 
         vec
     }
 }
 
-/// Build a map from each class to a set of all identifiers/keywords within it.
-fn build_map(paths: &[Path]) -> HashMap<String, HashSet<String>> {
+/// A type declaration, along with where it was declared.
+struct TypeDecl {
+    name: String,
+    kind: TypeKind,
+    type_params: Vec<String>,
+    context: Context,
+    /// From the name to the end of the declaration.
+    span: Span,
+    refs: Refs,
+}
+
+/// The names referenced by a type, each with the strongest kind of
+/// relationship it was found in and where it was first found.
+type Refs = HashMap<String, (EdgeKind, Span)>;
+
+/// A type name as written, such as `Foo.Bar<Baz>`. Dotted names are kept
+/// together, and the names within the type arguments are flattened into
+/// `args`.
+struct TypeRef {
+    name: String,
+    span: Span,
+    args: Vec<TypeRef>,
+}
+
+/// Where a type is declared: the namespaces and using directives in scope,
+/// the types it is nested in, outermost first, and the file.
+#[derive(Clone)]
+struct Context {
+    scope: Vec<Level>,
+    outer: Vec<Enclosing>,
+    file: Path,
+}
+
+/// A type that encloses a nested type declaration.
+#[derive(Clone)]
+struct Enclosing {
+    name: String,
+    type_params: Vec<String>,
+}
+
+/// Every declaration of each type, keyed by full name. A partial type has one
+/// declaration per part, possibly spread over several files.
+type DeclMap = HashMap<String, Vec<TypeDecl>>;
+
+impl TypeDecl {
+    /// The name the type is known by when resolving references, with the
+    /// number of type parameters appended CLR style, e.g. `Ns.Result`1`, and
+    /// nested types following the types enclosing them, e.g. `Ns.Outer.Inner`.
+    fn full_name(&self) -> String {
+        let mut names: Vec<String> = self.context.outer.iter()
+            .map(|o| arity_name(&o.name[], &o.type_params[]))
+            .collect();
+        names.push(arity_name(&self.name[], &self.type_params[]));
+        qualify(&self.context.namespace()[], &names.connect(".")[])
+    }
+
+    /// The name the type is shown with, e.g. `Ns.Outer.Result<T>`.
+    fn display_name(&self) -> String {
+        let mut names: Vec<String> = self.context.outer.iter()
+            .map(|o| generic_name(&o.name[], &o.type_params[]))
+            .collect();
+        names.push(generic_name(&self.name[], &self.type_params[]));
+        qualify(&self.context.namespace()[], &names.connect(".")[])
+    }
+
+    /// The full names of the types whose nested types can be referred to
+    /// by simple name from within this one: the type itself, followed by the
+    /// types enclosing it from the inside out.
+    fn enclosing_types(&self) -> Vec<String> {
+        let mut types = vec![self.full_name()];
+        let mut names: Vec<String> = self.context.outer.iter()
+            .map(|o| arity_name(&o.name[], &o.type_params[]))
+            .collect();
+        while !names.is_empty() {
+            types.push(qualify(&self.context.namespace()[], &names.connect(".")[]));
+            names.pop();
+        }
+        types
+    }
+
+    /// A place in the file the type is declared in.
+    fn location(&self, span: Span) -> Location {
+        Location { file: self.context.file.clone(), span: span }
+    }
+
+    /// Whether the name is a type parameter of this type or of the types
+    /// enclosing it.
+    fn is_type_param(&self, name: &str) -> bool {
+        self.type_params.iter().chain(self.context.outer.iter().flat_map(|o| o.type_params.iter()))
+            .any(|param| &param[] == name)
+    }
+}
+
+impl Context {
+    fn namespace(&self) -> String {
+        self.scope.last().unwrap().namespace.clone()
+    }
+}
+
+/// A type's name with its number of type parameters appended, e.g. `Result`1`.
+fn arity_name(name: &str, type_params: &[String]) -> String {
+    if type_params.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}`{}", name, type_params.len())
+    }
+}
+
+/// A type's name with its type parameters, e.g. `Result<T>`.
+fn generic_name(name: &str, type_params: &[String]) -> String {
+    if type_params.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}<{}>", name, type_params.connect(", "))
+    }
+}
+
+/// One level of namespace nesting: the compilation unit itself, or a
+/// `namespace` block. Using directives belong to the level they appear in.
+#[derive(Clone)]
+struct Level {
+    /// Fully qualified name of the namespace, empty for the compilation unit.
+    namespace: String,
+    /// Brace depth the namespace block was opened at, or None if it lasts
+    /// until the end of the file (the compilation unit, or `namespace X;`).
+    depth: Option<usize>,
+    usings: Vec<String>,
+    aliases: HashMap<String, String>,
+}
+
+impl Level {
+    fn new(namespace: String, depth: Option<usize>) -> Level {
+        Level { namespace: namespace, depth: depth, usings: vec![], aliases: HashMap::new() }
+    }
+}
+
+enum UsingDirective {
+    Import(String),
+    Alias(String, String),
+}
+
+/// Join a namespace and a name, e.g. `("A.B", "C")` gives `"A.B.C"`.
+fn qualify(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", namespace, name)
+    }
+}
+
+/// Build the graph from the declarations, keyed by fully qualified name. An
+/// edge A -> B means a reference inside A resolved to B, and carries the
+/// strongest kind among those references along with where the first of them
+/// is. All parts of a partial type make up a single node, each part's
+/// references resolved in its own scope.
+fn link(map: &DeclMap, ignore_bcl: bool) -> Graph<TypeNode, EdgeInfo> {
+    let mut g = IndexedGraph::new();
+
+    let mut names: Vec<&String> = map.keys().collect();
+    names.sort();
+
+    for name in names.iter() {
+        let decls = map.get(*name).unwrap();
+        g.get_or_add_node(&name[], || TypeNode {
+            name: decls[0].display_name(),
+            kind: decls[0].kind,
+            namespace: decls[0].context.namespace(),
+            project: None,
+            locations: decls.iter().map(|decl| decl.location(decl.span)).collect(),
+            markers: vec![],
+        });
+    }
+
+    // The nested types directly inside each type.
+    let mut children: HashMap<String, Vec<String>> = HashMap::new();
+    for name in names.iter() {
+        let types = map.get(*name).unwrap()[0].enclosing_types();
+        if types.len() > 1 {
+            if !children.contains_key(&types[1]) {
+                children.insert(types[1].clone(), vec![]);
+            }
+            children.get_mut(&types[1]).unwrap().push(name.to_string());
+        }
+    }
+
+    for name in names.iter() {
+        let mut targets: HashMap<String, (EdgeKind, Location)> = HashMap::new();
+        if let Some(nested) = children.get(*name) {
+            for child in nested.iter() {
+                let decl = &map.get(child).unwrap()[0];
+                add_target(&mut targets, child.clone(), Contains, decl.location(decl.span));
+            }
+        }
+
+        for decl in map.get(*name).unwrap().iter() {
+            let types = decl.enclosing_types();
+            let mut refs: Vec<(&String, &(EdgeKind, Span))> = decl.refs.iter().collect();
+            refs.sort_by(|a, b| (a.1).1.start.cmp(&(b.1).1.start));
+            for &(reference, &(kind, span)) in refs.iter() {
+                let first = reference.split('.').next().unwrap();
+                if decl.is_type_param(first) || (ignore_bcl && is_bcl_type(first)) {
+                    continue;
+                }
+                if let Some((target, len)) = resolve(&reference[], &types[], &decl.context.scope[], map) {
+                    let target_kind = map.get(&target).unwrap()[0].kind;
+                    let kind = if kind == Inheritance && target_kind == Interface && decl.kind != Interface {
+                        Implementation
+                    } else if kind == Reference && len < reference.split('.').count() {
+                        // Something like `Logger.Instance` accesses a member of the type.
+                        StaticAccess
+                    } else {
+                        kind
+                    };
+                    add_target(&mut targets, target, kind, decl.location(span));
+                }
+            }
+        }
+
+        let mut targets: Vec<(String, (EdgeKind, Location))> = targets.into_iter().collect();
+        targets.sort_by(|a, b| a.0.cmp(&b.0));
+        for (target, (kind, location)) in targets.into_iter() {
+            let info = EdgeInfo { kind: kind, location: location, weight: 1, implied: false };
+            let (source, target) = (g.find_node(&name[]).unwrap(), g.find_node(&target[]).unwrap());
+            g.add_or_update_edge(source, target, info, |info, other| info.merge(other));
+        }
+    }
+
+    g.into_graph()
+}
+
+/// Record the target of an edge, keeping the strongest kind and the first
+/// location it was seen at.
+fn add_target(targets: &mut HashMap<String, (EdgeKind, Location)>, target: String, kind: EdgeKind,
+              location: Location) {
+    let entry = match targets.get(&target) {
+        Some(&(seen, ref first)) => (if seen < kind { seen } else { kind }, first.clone()),
+        None => (kind, location),
+    };
+    targets.insert(target, entry);
+}
+
+/// Whether the first part of a reference names a well known type of the
+/// base class library, or its `System` namespace.
+fn is_bcl_type(first: &str) -> bool {
+    let name = first.split('`').next().unwrap();
+    name == "System" || BCL_TYPES.contains(&name)
+}
+
+/// Resolve a possibly dotted reference to the fully qualified name of a known
+/// type, along with how many parts of the reference named it. The longest
+/// resolvable prefix wins, so `Outer.Inner` is preferred over `Outer`, and a
+/// member access like `Logger.Instance` falls back to `Logger`.
+fn resolve(reference: &str, types: &[String], scope: &[Level], known: &DeclMap) -> Option<(String, usize)> {
+    let parts: Vec<&str> = reference.split('.').collect();
+    for n in range(1, parts.len() + 1).rev() {
+        let name = parts[..n].connect(".");
+        if let Some(full) = resolve_name(&name[], types, scope, known) {
+            return Some((full, n));
+        }
+    }
+    None
+}
+
+/// Look a name up the way the C# compiler would: first among the types
+/// nested in the enclosing types, then starting from the innermost namespace,
+/// try the namespace's own members, then its aliases, then the namespaces it
+/// imports, before moving outwards. A dotted declaration like `namespace A.B`
+/// also declares `A`, whose members are tried before the enclosing level's.
+fn resolve_name(name: &str, types: &[String], scope: &[Level], known: &DeclMap) -> Option<String> {
+    let first = name.split('.').next().unwrap();
+    let rest = &name[first.len()..];
+
+    for enclosing in types.iter() {
+        let candidate = qualify(&enclosing[], name);
+        if known.contains_key(&candidate) {
+            return Some(candidate);
+        }
+    }
+
+    for (i, level) in scope.iter().enumerate().rev() {
+        let candidate = qualify(&level.namespace[], name);
+        if known.contains_key(&candidate) {
+            return Some(candidate);
+        }
+
+        if let Some(target) = level.aliases.get(first) {
+            let candidate = format!("{}{}", target, rest);
+            if known.contains_key(&candidate) {
+                return Some(candidate);
+            }
+        }
+
+        for using in level.usings.iter() {
+            // Importing a namespace brings in its types but not the
+            // namespaces inside it, so with `using A;` the name `B.C` only
+            // resolves to `A.B.C` when `A.B` is a type.
+            if !known.contains_key(&qualify(&using[], first)) {
+                continue;
+            }
+            let candidate = qualify(&using[], name);
+            if known.contains_key(&candidate) {
+                return Some(candidate);
+            }
+        }
+
+        let outer = if i > 0 { scope[i - 1].namespace.len() } else { 0 };
+        let mut namespace = &level.namespace[];
+        while let Some(dot) = namespace.rfind('.') {
+            namespace = &namespace[..dot];
+            if namespace.len() <= outer {
+                break;
+            }
+            let candidate = qualify(namespace, name);
+            if known.contains_key(&candidate) {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+/// Build a map from the fully qualified name of each type to its
+/// declarations, given each file along with the preprocessor symbols defined
+/// for it.
+fn build_map(files: &[(&Path, &HashSet<String>)]) -> DeclMap {
     let lexer = build_csharp_lexer();
     let mut map = HashMap::new();
 
-    for path in paths.iter() {
+    for &(path, defines) in files.iter() {
         let text = match File::open(path) {
             Err(_) => {
                 println!("Failed to open file");
@@ -78,71 +470,962 @@ fn build_map(paths: &[Path]) -> HashMap<String, HashSet<String>> {
             },
         };
 
-        let mut tokens = lexer.lex(&text[]);
-        loop {
-            if let Some(tok) = tokens.next() {
-                match (tok.0, tok.1) {
-                    (Matched(IdentifierOrKeyword), txt) => {
-                        if txt == "class" || txt == "struct" {
-                            if let Some((classname, wordset)) = class_x(&mut tokens) {
-                                map.insert(classname, wordset);
-                            }
-                        }
+        let mut tokens = TokenStream::new(lexer.lex(&text[]), defines);
+        add_decls(&mut map, parse_file(&mut tokens, path));
+    }
+
+    map
+}
+
+fn add_decls(map: &mut DeclMap, decls: Vec<TypeDecl>) {
+    for decl in decls.into_iter() {
+        let name = decl.full_name();
+        if !map.contains_key(&name) {
+            map.insert(name.clone(), vec![]);
+        }
+        map.get_mut(&name).unwrap().push(decl);
+    }
+}
+
+/// Walk through a file keeping track of namespace blocks and using
+/// directives, and parse each type found along the way.
+fn parse_file(tokens: &mut TokenStream, file: &Path) -> Vec<TypeDecl> {
+    let mut decls = vec![];
+    let mut levels = vec![Level::new(String::new(), None)];
+    let mut block_depth = 0;
+
+    while let Some(tok) = tokens.next() {
+        match (tok.0, &tok.1[]) {
+            (Matched(Keyword), "namespace") => {
+                let name = unwrap_or_return!(qualified_name(tokens), decls);
+                let full = qualify(&levels.last().unwrap().namespace[], &name[]);
+                match tokens.next() {
+                    Some((Matched(BlockBegin), _)) => {
+                        block_depth += 1;
+                        levels.push(Level::new(full, Some(block_depth)));
                     },
-                    _ => {},
+                    Some((Matched(Semicolon), _)) => {
+                        levels.push(Level::new(full, None));
+                    },
+                    _ => return decls,
                 }
-            } else {
-                break;
-            }
+            },
+            (Matched(Keyword), "using") => {
+                let level = levels.last_mut().unwrap();
+                match using_directive(tokens) {
+                    Some(Import(namespace)) => { level.usings.push(namespace); },
+                    Some(Alias(alias, target)) => { level.aliases.insert(alias, target); },
+                    None => {},
+                }
+            },
+            (Matched(Keyword), word) | (Matched(Identifier), word) => {
+                if let Some(kind) = declared_kind(word, tokens) {
+                    let ctx = Context { scope: levels.clone(), outer: vec![], file: file.clone() };
+                    let mut nested = vec![];
+                    let parsed = if kind == Delegate {
+                        delegate_x(tokens, &ctx)
+                    } else {
+                        class_x(tokens, kind, &ctx, &mut nested)
+                    };
+                    decls.extend(parsed.into_iter());
+                    decls.extend(nested.into_iter());
+                }
+            },
+            (Matched(BlockBegin), _) => { block_depth += 1; },
+            (Matched(BlockEnd), _) => {
+                if levels.last().unwrap().depth == Some(block_depth) {
+                    levels.pop();
+                }
+                if block_depth > 0 {
+                    block_depth -= 1;
+                }
+            },
+            _ => {},
         }
     }
 
-    map
+    decls
+}
+
+/// Parse the rest of a using directive, starting after `using` and ending at
+/// the `;`. Handles `using X.Y;`, `using static X.Y;` and `using A = X.Y;`.
+fn using_directive(tokens: &mut TokenStream) -> Option<UsingDirective> {
+    if tokens.peek_text() == Some("static") {
+        tokens.next();
+    }
+
+    let name = unwrap_or_return!(qualified_name(tokens), None);
+    let tok = unwrap_or_return!(tokens.next(), None);
+    match tok.0 {
+        Matched(Semicolon) => Some(Import(name)),
+        Matched(Equals) => {
+            let target = unwrap_or_return!(qualified_name(tokens), None);
+            // Skip anything else, such as the type arguments of a generic alias.
+            while let Some(tok) = tokens.next() {
+                if tok.0 == Matched(Semicolon) {
+                    break;
+                }
+            }
+            Some(Alias(name, target))
+        },
+        _ => None,
+    }
 }
 
-/// parse class _ { ... }, starting at _, ending at }, returning the name of the class
-/// and the set of identifier/keyword tokens within "...".
-fn class_x(tokens: &mut TokenIterator<TokenClass>) -> Option<(String, HashSet<String>)> {
-    let mut set = HashSet::new();
+/// Parse a dotted name such as `System.Collections.Generic`.
+fn qualified_name(tokens: &mut TokenStream) -> Option<String> {
+    let mut name = match tokens.next() {
+        Some((Matched(Identifier), x)) => identifier_name(&x[]).to_string(),
+        _ => return None,
+    };
+
+    while tokens.peek_class() == Some(Matched(Dot)) && tokens.peek_class_nth(1) == Some(Matched(Identifier)) {
+        tokens.next();
+        name.push('.');
+        name.push_str(identifier_name(&tokens.next().unwrap().1[]));
+    }
+
+    Some(name)
+}
 
+/// The name an identifier stands for, without the `@` that lets a verbatim
+/// identifier such as `@class` be spelled like a keyword.
+fn identifier_name(text: &str) -> &str {
+    if text.starts_with("@") { &text[1..] } else { text }
+}
+
+/// parse class _<...> : bases where ... { ... }, starting at _, ending at },
+/// returning the declaration with the names referenced within, each with the
+/// strongest kind of relationship it was found in. Dotted names such as
+/// `Foo.Bar` are kept together as one entry. Records and primary constructors
+/// may have a parameter list after the name, and records may end with `;`
+/// instead of a body. Types nested inside are pushed onto `nested`, and what
+/// they reference is theirs alone.
+fn class_x(tokens: &mut TokenStream, kind: TypeKind, ctx: &Context, nested: &mut Vec<TypeDecl>) -> Option<TypeDecl> {
+    let mut refs = HashMap::new();
+
+    let start = unwrap_or_return!(tokens.span(), None);
     let classname = {
-        let tok = unwrap_or_return!(next_meaningful(tokens), None);
+        let tok = unwrap_or_return!(tokens.next(), None);
         match (tok.0, tok.1) {
-            (Matched(IdentifierOrKeyword), x) => x.to_string(),
+            (Matched(Identifier), x) => identifier_name(&x[]).to_string(),
             _ => return None,
         }
     };
 
-    let tok2 = unwrap_or_return!(next_meaningful(tokens), None);
-    match tok2.0 {
-        Matched(BlockBegin) => {},
+    let type_params = unwrap_or_return!(type_parameters(tokens), None);
+
+    if tokens.peek_class() == Some(Matched(ParenBegin)) {
+        // The parameters of a record become its properties.
+        let param_kind = if kind == Record || kind == RecordStruct { Field } else { Parameter };
+        unwrap_or_return!(delimited(tokens, ParenBegin, ParenEnd, param_kind, &mut refs), None);
+    }
+
+    if tokens.peek_class() == Some(Matched(Colon)) {
+        tokens.next();
+        unwrap_or_return!(base_list(tokens, &mut refs), None);
+    }
+
+    if tokens.peek_text() == Some("where") {
+        unwrap_or_return!(constraints(tokens, &mut refs), None);
+    }
+
+    let tok2 = unwrap_or_return!(tokens.next(), None);
+    let has_body = match tok2.0 {
+        Matched(BlockBegin) => true,
+        Matched(Semicolon) if kind == Record || kind == RecordStruct => false,
         _ => return None,
+    };
+
+    if has_body && kind == Enum {
+        unwrap_or_return!(code(tokens, true, &mut refs), None);
+    } else if has_body {
+        let mut inner = ctx.clone();
+        inner.outer.push(Enclosing { name: classname.clone(), type_params: type_params.clone() });
+        unwrap_or_return!(class_body(tokens, &mut refs, &inner, nested), None);
     }
 
-    let mut block_depth = 1;
-    
-    while block_depth >= 0 {
-        let tok = unwrap_or_return!(next_meaningful(tokens), None);
-        match (tok.0, tok.1) {
-            (Matched(BlockBegin), x) => { block_depth += 1; },
-            (Matched(BlockEnd), x) => { block_depth -= 1; },
-            (Matched(IdentifierOrKeyword), x) => { set.insert(x.to_string()); },
+    Some(TypeDecl {
+        name: classname,
+        kind: kind,
+        type_params: type_params,
+        context: ctx.clone(),
+        span: start.to(tokens.last_span().unwrap()),
+        refs: refs,
+    })
+}
+
+/// parse delegate R _<...>(...) where ...; starting at R, ending at ;, returning
+/// the declaration with the types in its signature.
+fn delegate_x(tokens: &mut TokenStream, ctx: &Context) -> Option<TypeDecl> {
+    let mut refs = HashMap::new();
+    let mut names = vec![];
+
+    loop {
+        match unwrap_or_return!(tokens.peek_class(), None) {
+            Matched(Identifier) => { names.push(type_reference(tokens).unwrap()); },
+            Matched(ParenBegin) => break,
+            Matched(Semicolon) | Matched(BlockBegin) | Matched(BlockEnd) => return None,
+            _ => { tokens.next(); },
+        }
+    }
+
+    // The last name is the delegate's own, whose type arguments are really
+    // its type parameters.
+    let own = unwrap_or_return!(names.pop(), None);
+    let name = own.name.split('`').next().unwrap().to_string();
+    let type_params = own.args.into_iter().map(|arg| arg.name).collect();
+
+    for return_type in names.into_iter() {
+        add_refs(&mut refs, return_type, Parameter);
+    }
+    unwrap_or_return!(delimited(tokens, ParenBegin, ParenEnd, Parameter, &mut refs), None);
+
+    if tokens.peek_text() == Some("where") {
+        unwrap_or_return!(constraints(tokens, &mut refs), None);
+    }
+
+    match tokens.next() {
+        Some((Matched(Semicolon), _)) => Some(TypeDecl {
+            name: name,
+            kind: Delegate,
+            type_params: type_params,
+            context: ctx.clone(),
+            span: own.span.to(tokens.last_span().unwrap()),
+            refs: refs,
+        }),
+        _ => None,
+    }
+}
+
+/// The kind of type a keyword declares, looking past `record` to tell a
+/// `record struct` from a `record class`.
+fn declared_kind(keyword: &str, tokens: &mut TokenStream) -> Option<TypeKind> {
+    match keyword {
+        "class" => Some(Class),
+        "struct" => Some(Struct),
+        "interface" => Some(Interface),
+        "enum" => Some(Enum),
+        "delegate" => Some(Delegate),
+        "record" => {
+            match tokens.peek_text() {
+                Some("struct") => {
+                    tokens.next();
+                    Some(RecordStruct)
+                },
+                Some("class") => {
+                    tokens.next();
+                    Some(Record)
+                },
+                _ => Some(Record),
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Parse a type parameter list such as `<in T, U>` after the name of a type,
+/// if there is one, returning the names of the parameters.
+fn type_parameters(tokens: &mut TokenStream) -> Option<Vec<String>> {
+    let mut params = vec![];
+    if tokens.peek_class() != Some(Matched(LessThan)) {
+        return Some(params);
+    }
+    tokens.next();
+
+    loop {
+        match unwrap_or_return!(tokens.next(), None) {
+            (Matched(GreaterThan), _) => return Some(params),
+            (Matched(Identifier), x) => { params.push(identifier_name(&x[]).to_string()); },
+            (Matched(BracketBegin), _) => {
+                // Skip attributes on the parameter.
+                while let Some(tok) = tokens.next() {
+                    if tok.0 == Matched(BracketEnd) {
+                        break;
+                    }
+                }
+            },
             _ => {},
         }
     }
+}
+
+/// Parse the base list after `class X :`, up to but not including the `{`
+/// (or the `;` ending a record, or the `where` starting constraints). Entries
+/// are recorded as inherited for now, and told apart from implemented
+/// interfaces once every type is known. Their type arguments are plain
+/// references.
+fn base_list(tokens: &mut TokenStream, refs: &mut Refs) -> Option<()> {
+    let mut entry_start = true;
 
-    Some((classname, set))
+    loop {
+        match unwrap_or_return!(tokens.peek_class(), None) {
+            Matched(BlockBegin) | Matched(Semicolon) => return Some(()),
+            Matched(Comma) => {
+                tokens.next();
+                entry_start = true;
+            },
+            Matched(Identifier) => {
+                if tokens.peek_text() == Some("where") {
+                    return Some(());
+                }
+                let base = type_reference(tokens).unwrap();
+                add_ref(refs, base.name, base.span, if entry_start { Inheritance } else { Reference });
+                for arg in base.args.into_iter() {
+                    add_refs(refs, arg, Reference);
+                }
+                entry_start = false;
+            },
+            _ => { tokens.next(); },
+        }
+    }
+}
+
+/// Parse `where` clauses such as `where T : IEntity, new()`, up to but not
+/// including the `{` or `;` that follows them.
+fn constraints(tokens: &mut TokenStream, refs: &mut Refs) -> Option<()> {
+    loop {
+        match unwrap_or_return!(tokens.peek_class(), None) {
+            Matched(BlockBegin) | Matched(Semicolon) => return Some(()),
+            Matched(Identifier) => {
+                if tokens.peek_text() == Some("where") {
+                    // Skip the keyword and the type parameter being constrained.
+                    tokens.next();
+                    tokens.next();
+                } else {
+                    add_refs(refs, type_reference(tokens).unwrap(), Constraint);
+                }
+            },
+            _ => { tokens.next(); },
+        }
+    }
+}
+
+/// Parse the members of a class, starting after the `{` and ending at the
+/// matching `}`. Names are classified by where they appear: the types of
+/// fields and properties, the types in method signatures, and anything
+/// referenced from method bodies and initializers. Nested types are parsed
+/// in the given context and pushed onto `nested`.
+fn class_body(tokens: &mut TokenStream, refs: &mut Refs, ctx: &Context,
+              nested: &mut Vec<TypeDecl>) -> Option<()> {
+    // The names seen so far in the member being declared.
+    let mut member: Vec<TypeRef> = vec![];
+    // Whether the member's parameter list has been seen, i.e. it is a method.
+    let mut signature = false;
+    // The type parameters of the method being declared, and the names its
+    // signature and body refer to, kept apart until the method ends so that
+    // those naming its type parameters can be left out.
+    let mut method_params: Vec<String> = vec![];
+    let mut method_refs: Refs = HashMap::new();
+
+    loop {
+        match unwrap_or_return!(tokens.peek_class(), None) {
+            Matched(BlockEnd) => {
+                tokens.next();
+                return Some(());
+            },
+            Matched(Keyword) | Matched(Identifier) if !signature && starts_type_decl(tokens, &member[]) => {
+                // A nested type. Its modifiers are of no interest.
+                let word = tokens.next().unwrap().1;
+                let kind = declared_kind(&word[], tokens).unwrap();
+                let parsed = if kind == Delegate {
+                    delegate_x(tokens, ctx)
+                } else {
+                    class_x(tokens, kind, ctx, nested)
+                };
+                nested.extend(parsed.into_iter());
+                member.clear();
+            },
+            Matched(Identifier) => {
+                let reference = type_reference(tokens).unwrap();
+                if signature {
+                    add_refs(&mut method_refs, reference, Parameter);
+                } else {
+                    member.push(reference);
+                }
+            },
+            Matched(Keyword) if !signature && ["this", "operator"].contains(&tokens.peek_text().unwrap()) => {
+                // Stands in for the name of an indexer or operator, so the
+                // type before it is still taken as the member's type.
+                let word = tokens.next().unwrap().1;
+                member.push(TypeRef { name: word, span: tokens.last_span().unwrap(), args: vec![] });
+            },
+            Matched(BracketBegin) => {
+                // An attribute section, or the parameters of an indexer.
+                let kind = if member.is_empty() { Reference } else { Parameter };
+                let target = if signature { &mut method_refs } else { &mut *refs };
+                unwrap_or_return!(delimited(tokens, BracketBegin, BracketEnd, kind, target), None);
+            },
+            Matched(ParenBegin) => {
+                // The last name is the method's, the ones before it make up
+                // the return type.
+                if !signature {
+                    method_params = member.last().map_or(vec![], |name| {
+                        name.args.iter().map(|param| param.name.clone()).collect()
+                    });
+                    let len = member.len();
+                    flush_member(&mut member, len.saturating_sub(1), Parameter, &mut method_refs);
+                    signature = true;
+                }
+                unwrap_or_return!(delimited(tokens, ParenBegin, ParenEnd, Parameter, &mut method_refs), None);
+            },
+            Matched(Semicolon) => {
+                tokens.next();
+                let len = member.len();
+                flush_member(&mut member, len.saturating_sub(1), Field, refs);
+                end_method(&mut method_refs, &mut method_params, refs);
+                signature = false;
+            },
+            Matched(Equals) => {
+                // A field initializer or an expression bodied member.
+                tokens.next();
+                let len = member.len();
+                flush_member(&mut member, len.saturating_sub(1), Field, refs);
+                {
+                    let target = if signature { &mut method_refs } else { &mut *refs };
+                    unwrap_or_return!(code(tokens, false, target), None);
+                }
+                end_method(&mut method_refs, &mut method_params, refs);
+                signature = false;
+            },
+            Matched(BlockBegin) => {
+                // A method body or property accessors.
+                tokens.next();
+                let len = member.len();
+                flush_member(&mut member, len.saturating_sub(1), Field, refs);
+                {
+                    let target = if signature { &mut method_refs } else { &mut *refs };
+                    unwrap_or_return!(code(tokens, true, target), None);
+                }
+                end_method(&mut method_refs, &mut method_params, refs);
+                signature = false;
+            },
+            _ => { tokens.next(); },
+        }
+    }
+}
+
+/// Whether the next token starts a type declaration. `record` is only a
+/// contextual keyword, so it's also the name of a field or property in
+/// `Record record;`, and only starts a declaration at the start of a member,
+/// past any modifiers, when a name or `class` or `struct` follows.
+fn starts_type_decl(tokens: &TokenStream, member: &[TypeRef]) -> bool {
+    match tokens.peek_text() {
+        Some("record") => {
+            member.iter().all(|reference| CONTEXTUAL_KEYWORDS.contains(&&reference.name[])) &&
+                (tokens.peek_class_nth(1) == Some(Matched(Identifier)) ||
+                 ["class", "struct"].contains(&tokens.peek_nth(1).map_or("", |tok| &tok.1[])))
+        },
+        Some(word) => ["class", "struct", "interface", "enum", "delegate"].contains(&word),
+        None => false,
+    }
+}
+
+/// Record the references of a method once it ends, leaving out those naming
+/// its own type parameters, such as `T` in `T Get<T>(T x) where T : IFoo`.
+fn end_method(method_refs: &mut Refs, params: &mut Vec<String>, refs: &mut Refs) {
+    for (name, (kind, span)) in replace(method_refs, HashMap::new()).into_iter() {
+        if !params.iter().any(|param| &param[] == name.split('.').next().unwrap()) {
+            add_ref(refs, name, span, kind);
+        }
+    }
+    params.clear();
+}
+
+/// Record the first `n` names of a member declaration as references of the
+/// given kind, and forget the rest.
+fn flush_member(member: &mut Vec<TypeRef>, n: usize, kind: EdgeKind, refs: &mut Refs) {
+    for reference in member.drain().take(n) {
+        add_refs(refs, reference, kind);
+    }
+}
+
+/// Parse from an opening bracket to its matching closing one, recording each
+/// name within as a reference of the given kind.
+fn delimited(tokens: &mut TokenStream, open: TokenClass, close: TokenClass, kind: EdgeKind,
+             refs: &mut Refs) -> Option<()> {
+    let mut depth = 0;
+
+    loop {
+        let class = unwrap_or_return!(tokens.peek_class(), None);
+        if class == Matched(Identifier) {
+            add_refs(refs, type_reference(tokens).unwrap(), kind);
+            continue;
+        }
+
+        tokens.next();
+        if class == Matched(open.clone()) {
+            depth += 1;
+        } else if class == Matched(close.clone()) {
+            depth -= 1;
+            if depth == 0 {
+                return Some(());
+            }
+        }
+    }
+}
+
+/// Parse code such as a method body or an initializer. With `in_block` this
+/// starts after a `{` and ends at the matching `}`, otherwise it ends at the
+/// `;` finishing the expression. The type following `new` is recorded as
+/// instantiated, other names as plain references. Names following a `.`
+/// such as in `this.logger` or `Get().Value` are members, not types.
+fn code(tokens: &mut TokenStream, in_block: bool, refs: &mut Refs) -> Option<()> {
+    let mut depth = 0;
+    let mut after_new = false;
+    let mut after_dot = false;
+
+    loop {
+        match unwrap_or_return!(tokens.peek_class(), None) {
+            Matched(Keyword) => {
+                if tokens.next().unwrap().1 == "new" {
+                    after_new = true;
+                    continue;
+                }
+            },
+            Matched(Identifier) => {
+                let reference = type_reference(tokens).unwrap();
+                if !after_dot {
+                    add_ref(refs, reference.name, reference.span, if after_new { Instantiation } else { Reference });
+                }
+                for arg in reference.args.into_iter() {
+                    add_refs(refs, arg, Reference);
+                }
+            },
+            Matched(Dot) => {
+                tokens.next();
+                after_dot = true;
+                continue;
+            },
+            Matched(BlockBegin) => {
+                tokens.next();
+                depth += 1;
+            },
+            Matched(BlockEnd) => {
+                if depth == 0 {
+                    // A missing `;` shouldn't swallow the end of the class.
+                    if in_block {
+                        tokens.next();
+                    }
+                    return Some(());
+                }
+                tokens.next();
+                depth -= 1;
+            },
+            Matched(Semicolon) if !in_block && depth == 0 => {
+                tokens.next();
+                return Some(());
+            },
+            _ => { tokens.next(); },
+        }
+        after_new = false;
+        after_dot = false;
+    }
+}
+
+/// Parse a reference such as `Foo`, `Ns.Foo<Bar>` or `Foo.Instance`, starting
+/// at its first identifier. Type arguments are folded into the name as an
+/// arity suffix like the CLR's `Foo`1`, so that generic and non-generic types
+/// of the same name stay apart, and the names within them are returned
+/// alongside since they are references too.
+fn type_reference(tokens: &mut TokenStream) -> Option<TypeRef> {
+    let start = unwrap_or_return!(tokens.span(), None);
+    let mut name = String::new();
+    let mut args = vec![];
+
+    loop {
+        match tokens.next() {
+            Some((Matched(Identifier), x)) => name.push_str(identifier_name(&x[])),
+            _ => return None,
+        }
+
+        if let Some(arity) = type_arguments(tokens, &mut args) {
+            name.push_str(&format!("`{}", arity)[]);
+        }
+
+        if tokens.peek_class() == Some(Matched(Dot)) && tokens.peek_class_nth(1) == Some(Matched(Identifier)) {
+            tokens.next();
+            name.push('.');
+        } else {
+            return Some(TypeRef { name: name, span: start.to(tokens.last_span().unwrap()), args: args });
+        }
+    }
+}
+
+/// Parse a type argument list such as `<Foo, Bar<Baz>>` if one comes next,
+/// pushing the names within onto `args` and returning the arity. Like the C#
+/// compiler, this looks ahead for a matching `>` followed by something that
+/// can come after a type, so comparisons like `a < b` are left alone.
+fn type_arguments(tokens: &mut TokenStream, args: &mut Vec<TypeRef>) -> Option<usize> {
+    if tokens.peek_class() != Some(Matched(LessThan)) {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut n = 0;
+    loop {
+        match tokens.peek_nth(n) {
+            Some(&(Matched(LessThan), _)) => { depth += 1; },
+            Some(&(Matched(GreaterThan), _)) => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            },
+            Some(&(Matched(Identifier), _)) | Some(&(Matched(Keyword), _)) |
+            Some(&(Matched(Dot), _)) | Some(&(Matched(Comma), _)) |
+            Some(&(Matched(BracketBegin), _)) | Some(&(Matched(BracketEnd), _)) |
+            Some(&(Matched(ParenBegin), _)) | Some(&(Matched(ParenEnd), _)) => {},
+            Some(&(Unmatched, ref x)) if &x[] == "?" => {},
+            _ => return None,
+        }
+        n += 1;
+    }
+    match tokens.peek_nth(n + 1) {
+        Some(&(Matched(StringLiteral), _)) => return None,
+        Some(&(Unmatched, ref x)) if &x[] != "?" => return None,
+        _ => {},
+    }
+
+    tokens.next();
+    let mut arity = 1;
+    let mut parens = 0;
+    loop {
+        match unwrap_or_return!(tokens.peek_class(), None) {
+            Matched(Identifier) => {
+                let mut arg = type_reference(tokens).unwrap();
+                let inner = replace(&mut arg.args, vec![]);
+                args.push(arg);
+                args.extend(inner.into_iter());
+            },
+            Matched(GreaterThan) => {
+                tokens.next();
+                return Some(arity);
+            },
+            Matched(ParenBegin) => {
+                tokens.next();
+                parens += 1;
+            },
+            Matched(ParenEnd) => {
+                tokens.next();
+                parens -= 1;
+            },
+            Matched(Comma) => {
+                tokens.next();
+                // Commas in a tuple type like `(int, string)` don't count.
+                if parens == 0 {
+                    arity += 1;
+                }
+            },
+            _ => { tokens.next(); },
+        }
+    }
+}
+
+/// Record a reference, keeping the strongest kind and the first span if it
+/// was already seen. Contextual keywords are skipped.
+fn add_ref(refs: &mut Refs, name: String, span: Span, kind: EdgeKind) {
+    if CONTEXTUAL_KEYWORDS.contains(&&name[]) {
+        return;
+    }
+    let entry = match refs.get(&name) {
+        Some(&(seen, first)) => (if seen < kind { seen } else { kind }, first),
+        None => (kind, span),
+    };
+    refs.insert(name, entry);
+}
+
+/// Record a reference along with its type arguments, all of the same kind.
+fn add_refs(refs: &mut Refs, reference: TypeRef, kind: EdgeKind) {
+    add_ref(refs, reference.name, reference.span, kind);
+    for arg in reference.args.into_iter() {
+        add_ref(refs, arg.name, arg.span, kind);
+    }
+}
+
+/// The state of the conditional directives (`#if`, `#elif`, `#else` and
+/// `#endif`) seen so far in a file.
+struct Conditions {
+    defines: HashSet<String>,
+    /// For each `#if` being inside of, whether one of its branches has been
+    /// taken, and whether the current branch is the one taken.
+    stack: Vec<(bool, bool)>,
+}
+
+impl Conditions {
+    fn new(defines: &HashSet<String>) -> Conditions {
+        Conditions { defines: defines.clone(), stack: vec![] }
+    }
+
+    /// Whether code at this point is compiled.
+    fn active(&self) -> bool {
+        self.stack.iter().all(|&(_, current)| current)
+    }
+
+    /// Update the state for a directive, given as the whole line. Directives
+    /// other than conditionals, `#define` and `#undef` are ignored, as are
+    /// stray `#elif`, `#else` and `#endif`.
+    fn directive(&mut self, line: &str) {
+        let line = line[1..].split_str("//").next().unwrap().trim();
+        let (name, rest) = match line.find(|c: char| c.is_whitespace()) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+
+        match name {
+            "define" if self.active() => { self.defines.insert(rest.to_string()); },
+            "undef" if self.active() => { self.defines.remove(rest); },
+            "if" => {
+                let outer = self.active();
+                let value = outer && evaluate(rest, &self.defines);
+                // Inside excluded code no branch is taken.
+                self.stack.push((value || !outer, value));
+            },
+            "elif" | "else" => {
+                if let Some((taken, _)) = self.stack.pop() {
+                    let value = !taken && self.active() && (name == "else" || evaluate(rest, &self.defines));
+                    self.stack.push((taken || value, value));
+                }
+            },
+            "endif" => { self.stack.pop(); },
+            _ => {},
+        }
+    }
+}
+
+/// Evaluate the condition of an `#if` or `#elif` given the defined symbols.
+/// A malformed condition counts as false.
+fn evaluate(condition: &str, defines: &HashSet<String>) -> bool {
+    let re = regex!(r"[\p{L}\p{Nd}_]+|&&|\|\||==|!=|!|\(|\)|\S");
+    let tokens: Vec<&str> = re.find_iter(condition).map(|(begin, end)| &condition[begin..end]).collect();
+    let mut pos = 0;
+    match or_condition(&tokens[], &mut pos, defines) {
+        Some(value) if pos == tokens.len() => value,
+        _ => false,
+    }
+}
+
+fn or_condition(tokens: &[&str], pos: &mut usize, defines: &HashSet<String>) -> Option<bool> {
+    let mut value = unwrap_or_return!(and_condition(tokens, pos, defines), None);
+    while tokens.get(*pos) == Some(&"||") {
+        *pos += 1;
+        let right = unwrap_or_return!(and_condition(tokens, pos, defines), None);
+        value = value || right;
+    }
+    Some(value)
+}
+
+fn and_condition(tokens: &[&str], pos: &mut usize, defines: &HashSet<String>) -> Option<bool> {
+    let mut value = unwrap_or_return!(equality_condition(tokens, pos, defines), None);
+    while tokens.get(*pos) == Some(&"&&") {
+        *pos += 1;
+        let right = unwrap_or_return!(equality_condition(tokens, pos, defines), None);
+        value = value && right;
+    }
+    Some(value)
+}
+
+fn equality_condition(tokens: &[&str], pos: &mut usize, defines: &HashSet<String>) -> Option<bool> {
+    let mut value = unwrap_or_return!(unary_condition(tokens, pos, defines), None);
+    loop {
+        let equal = match tokens.get(*pos) {
+            Some(&"==") => true,
+            Some(&"!=") => false,
+            _ => return Some(value),
+        };
+        *pos += 1;
+        let right = unwrap_or_return!(unary_condition(tokens, pos, defines), None);
+        value = (value == right) == equal;
+    }
+}
+
+fn unary_condition(tokens: &[&str], pos: &mut usize, defines: &HashSet<String>) -> Option<bool> {
+    let token = *unwrap_or_return!(tokens.get(*pos), None);
+    *pos += 1;
+    match token {
+        "!" => unary_condition(tokens, pos, defines).map(|value| !value),
+        "(" => {
+            let value = unwrap_or_return!(or_condition(tokens, pos, defines), None);
+            if tokens.get(*pos) != Some(&")") {
+                return None;
+            }
+            *pos += 1;
+            Some(value)
+        },
+        "true" => Some(true),
+        "false" => Some(false),
+        symbol if symbol.chars().all(|c| c.is_alphanumeric() || c == '_') => Some(defines.contains(symbol)),
+        _ => None,
+    }
+}
+
+/// The name of the project a source file belongs to: that of the project file
+/// in the closest directory above it.
+fn project_of(file: &Path, projects: &[Path]) -> Option<String> {
+    closest_project(file, projects).and_then(|i| projects[i].filestem_str()).map(|stem| stem.to_string())
+}
+
+/// The index of the project file in the closest directory above a source file.
+fn closest_project(file: &Path, projects: &[Path]) -> Option<usize> {
+    let mut closest: Option<usize> = None;
+    for (i, project) in projects.iter().enumerate() {
+        let dir = project.dir_path();
+        if dir.is_ancestor_of(file) && closest.map_or(true, |c| projects[c].dir_path().is_ancestor_of(&dir)) {
+            closest = Some(i);
+        }
+    }
+    closest
+}
+
+/// The preprocessor symbols defined by a project file's `DefineConstants` in
+/// the given configuration.
+fn project_defines(path: &Path, configuration: &str) -> Vec<String> {
+    let text = match File::open(path) {
+        Err(e) => {
+            let _ = writeln!(&mut stderr(), "machete: {}: {}", path.display(), e);
+            return vec![];
+        },
+        Ok(mut file) => {
+            file.read_to_string().unwrap()
+        },
+    };
+    configuration_defines(&text[], configuration)
+}
+
+/// The symbols of the `DefineConstants` in the text of a project file that
+/// apply to the configuration. Property groups and elements conditioned on
+/// another configuration are left out, as are references to other properties
+/// such as `$(DefineConstants)`.
+fn configuration_defines(text: &str, configuration: &str) -> Vec<String> {
+    // The attributes are matched one by one, since a condition may hold `/`
+    // or `>`, while an empty `<PropertyGroup />` mustn't match at all.
+    let groups = regex!(r#"(?s)<PropertyGroup((?:\s+[\w.:-]+\s*=\s*(?:"[^"]*"|'[^']*'))*)\s*>(.*?)</PropertyGroup>"#);
+    let constants = regex!(r#"<DefineConstants((?:\s+[\w.:-]+\s*=\s*(?:"[^"]*"|'[^']*'))*)\s*>([^<]*)</DefineConstants>"#);
+    let mut defines = vec![];
+    for group in groups.captures_iter(text) {
+        if !applies_to(group.at(1).unwrap(), configuration) {
+            continue;
+        }
+        for caps in constants.captures_iter(group.at(2).unwrap()) {
+            if !applies_to(caps.at(1).unwrap(), configuration) {
+                continue;
+            }
+            for symbol in caps.at(2).unwrap().split(|c: char| c == ';' || c == ',') {
+                let symbol = symbol.trim();
+                if !symbol.is_empty() && !symbol.contains("$(") {
+                    defines.push(symbol.to_string());
+                }
+            }
+        }
+    }
+    defines
+}
+
+/// Whether an element with the given attributes applies to the configuration:
+/// it does unless its `Condition` compares `$(Configuration)` with `==` or
+/// `!=` and fails for the configuration's name, as
+/// `'$(Configuration)|$(Platform)' == 'Release|AnyCPU'` does for `Debug`.
+/// Conditions that can't be evaluated that way, such as `Exists(...)` or
+/// ones joined by `and`, are ignored.
+fn applies_to(attributes: &str, configuration: &str) -> bool {
+    let attribute = regex!(r#"Condition\s*=\s*(?:"([^"]*)"|'([^']*)')"#);
+    let comparison = regex!(r"^\s*'([^']*)'\s*(==|!=)\s*'([^']*)'\s*$");
+    let condition = match attribute.captures(attributes) {
+        Some(caps) => caps.at(1).or(caps.at(2)).unwrap().to_string(),
+        None => return true,
+    };
+    let caps = match comparison.captures(&condition[]) {
+        Some(caps) => caps,
+        None => return true,
+    };
+
+    // Only the part of the value in the position of `$(Configuration)` is
+    // compared, ignoring the platform and other properties. Like MSBuild,
+    // the comparison ignores case.
+    let properties: Vec<&str> = caps.at(1).unwrap().split('|').map(|p| p.trim()).collect();
+    let values: Vec<&str> = caps.at(3).unwrap().split('|').map(|v| v.trim()).collect();
+    let position = properties.iter().position(|p| p.to_ascii_lowercase() == "$(configuration)");
+    match position {
+        Some(i) if properties.len() == values.len() => {
+            let equal = values[i].to_ascii_lowercase() == configuration.to_ascii_lowercase();
+            equal == (caps.at(2).unwrap() == "==")
+        },
+        _ => true,
+    }
+}
+
+/// The meaningful tokens of a file, meaning everything but whitespace,
+/// newlines, comments, preprocessor directives and code left out by them,
+/// with a read position that allows looking ahead.
+struct TokenStream {
+    tokens: Vec<(Token<TokenClass>, String)>,
+    spans: Vec<Span>,
+    pos: usize,
+}
+
+impl TokenStream {
+    /// Take the tokens from the iterator, leaving out the code excluded by
+    /// conditional directives given the defined preprocessor symbols.
+    fn new(mut iter: TokenIterator<TokenClass>, defines: &HashSet<String>) -> TokenStream {
+        let mut conditions = Conditions::new(defines);
+        let mut tokens = vec![];
+        let mut spans = vec![];
+        while let Some((class, text, span)) = next_meaningful(&mut iter) {
+            if class == Matched(Directive) {
+                conditions.directive(&text[]);
+            } else if conditions.active() {
+                tokens.push((class, text));
+                spans.push(span);
+            }
+        }
+        TokenStream { tokens: tokens, spans: spans, pos: 0 }
+    }
+
+    fn next(&mut self) -> Option<(Token<TokenClass>, String)> {
+        if self.pos < self.tokens.len() {
+            self.pos += 1;
+            Some(self.tokens[self.pos - 1].clone())
+        } else {
+            None
+        }
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&(Token<TokenClass>, String)> {
+        self.tokens.get(self.pos + n)
+    }
+
+    fn peek_class_nth(&self, n: usize) -> Option<Token<TokenClass>> {
+        self.peek_nth(n).map(|tok| tok.0.clone())
+    }
+
+    fn peek_class(&self) -> Option<Token<TokenClass>> {
+        self.peek_class_nth(0)
+    }
+
+    fn peek_text(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|tok| &tok.1[])
+    }
+
+    /// Where the next token is.
+    fn span(&self) -> Option<Span> {
+        self.spans.get(self.pos).map(|span| *span)
+    }
+
+    /// Where the token last read is.
+    fn last_span(&self) -> Option<Span> {
+        if self.pos > 0 { Some(self.spans[self.pos - 1]) } else { None }
+    }
 }
 
 /// Get the next token that is not a whitespace, newline, or comment.
-fn next_meaningful(tokens: &mut TokenIterator<TokenClass>) -> Option<(Token<TokenClass>, String)> {
+fn next_meaningful(tokens: &mut TokenIterator<TokenClass>) -> Option<(Token<TokenClass>, String, Span)> {
     loop {
         let tok = unwrap_or_return!(tokens.next(), None);
         match (tok.0, tok.1) {
             (Matched(Whitespace), x) => {},
             (Matched(Newline), x) => {},
             (Matched(Comment), x) => {},
-            (a, b) => return Some((a, b.to_string())),
+            (a, b) => return Some((a, b.to_string(), tok.2)),
         }
     }
 }
@@ -152,60 +1435,682 @@ enum TokenClass {
     Whitespace,
     Newline,
     Comment,
+    Directive,
     StringLiteral,
     BlockBegin,
     BlockEnd,
-    IdentifierOrKeyword,
+    ParenBegin,
+    ParenEnd,
+    BracketBegin,
+    BracketEnd,
+    LessThan,
+    GreaterThan,
+    Dot,
+    Comma,
+    Colon,
+    Semicolon,
+    Equals,
+    Keyword,
+    Identifier,
 }
 
 fn build_csharp_lexer() -> Lexer<TokenClass> {
     let mut lexer = Lexer::new();
 
-    lexer.define_token(Whitespace, regex!(r"^(\p{Zs}|\x{0009}|\x{000B}\x{000C})"));
+    lexer.define_token(Whitespace, regex!(r"^(\p{Zs}|\x{0009}|\x{000B}|\x{000C})"));
     lexer.define_token(Newline, regex!(r"^((\r\n)|\r|\n|\x{2028}|\x{2029})"));
-    lexer.define_token(Comment, regex!(r"^((/\*[^\*/]\*/)|(//[^\x{000D}\x{000A}\x{2028}\x{2029}]*))"));
-    lexer.define_token(StringLiteral, regex!(r##"^"([^"\r\n\x{2028}\x{2029}\\]|\\'|\\"|\\\\|\\0|\\a|\\b|\\f|\\n|\\r|\\t|\\v|(\\x[0-9A-Fa-f]{1,4})|(\\u[0-9A-Fa-f]{4})|(\\U[0-9A-Fa-f]{8}))*""##));
+    lexer.define_token(Comment, regex!(r"^((?s:/\*.*?\*/)|(//[^\x{000D}\x{000A}\x{2028}\x{2029}]*))"));
+    lexer.define_token(Directive, regex!(r"^#[^\x{000D}\x{000A}\x{2028}\x{2029}]*"));
+    lexer.define_scanned_token(StringLiteral, string_literal);
+    lexer.define_scanned_token(StringLiteral, char_literal);
     lexer.define_token(BlockBegin, regex!(r"^\{"));
     lexer.define_token(BlockEnd, regex!(r"^\}"));
-    lexer.define_token(IdentifierOrKeyword, regex!(r"^((_|\p{L}|\p{Nl})(\p{L}|\p{Nl}\p{Nd}|\p{Pc}|\p{Mn}|\p{Mc}|\p{Cf})*)"));
+    lexer.define_token(ParenBegin, regex!(r"^\("));
+    lexer.define_token(ParenEnd, regex!(r"^\)"));
+    lexer.define_token(BracketBegin, regex!(r"^\["));
+    lexer.define_token(BracketEnd, regex!(r"^\]"));
+    lexer.define_token(LessThan, regex!(r"^<"));
+    lexer.define_token(GreaterThan, regex!(r"^>"));
+    lexer.define_token(Dot, regex!(r"^\."));
+    lexer.define_token(Comma, regex!(r"^,"));
+    lexer.define_token(Colon, regex!(r"^:"));
+    lexer.define_token(Semicolon, regex!(r"^;"));
+    lexer.define_token(Equals, regex!(r"^="));
+    lexer.define_token(Keyword, Regex::new(&format!(r"^({})\b", KEYWORDS.connect("|"))[]).unwrap());
+    lexer.define_token(Identifier, regex!(r"^(@?(_|\p{L}|\p{Nl})(\p{L}|\p{Nl}|\p{Nd}|\p{Pc}|\p{Mn}|\p{Mc}|\p{Cf})*)"));
 
     lexer
 }
 
+/// The length of the string literal starting the text, if there is one.
+/// Regular, verbatim (`@"..."`), interpolated (`$"...{x}..."`) and raw
+/// (`"""..."""`) strings are recognised, along with their combinations and
+/// a `u8` suffix. The holes of interpolated strings may themselves contain
+/// strings and braces.
+fn string_literal(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    let mut dollars = 0;
+    let mut verbatim = false;
+    loop {
+        match bytes.get(i) {
+            Some(&b'$') => dollars += 1,
+            Some(&b'@') if !verbatim => verbatim = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    if bytes.get(i) != Some(&b'"') || (verbatim && dollars > 1) {
+        return None;
+    }
+
+    let quotes = run_length(bytes, i, b'"');
+    let end = if verbatim || quotes == 1 {
+        unwrap_or_return!(quoted_string(text, i + 1, verbatim, dollars > 0), None)
+    } else if quotes == 2 {
+        i + 2
+    } else {
+        unwrap_or_return!(raw_string(text, i + quotes, quotes, dollars), None)
+    };
+
+    if text[end..].starts_with("u8") || text[end..].starts_with("U8") {
+        Some(end + 2)
+    } else {
+        Some(end)
+    }
+}
+
+/// Skip over the contents of a regular or verbatim string, starting after the
+/// opening quote, returning the position after the closing one.
+fn quoted_string(text: &str, start: usize, verbatim: bool, interpolated: bool) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = start;
+    loop {
+        match *unwrap_or_return!(bytes.get(i), None) {
+            b'"' if verbatim && bytes.get(i + 1) == Some(&b'"') => i += 2,
+            b'"' => return Some(i + 1),
+            b'\\' if !verbatim => i += 2,
+            b'\r' | b'\n' if !verbatim => return None,
+            b'{' if interpolated && bytes.get(i + 1) == Some(&b'{') => i += 2,
+            b'{' if interpolated => i = unwrap_or_return!(hole(text, i + 1, 1), None),
+            _ => i += 1,
+        }
+    }
+}
+
+/// Skip over the contents of a raw string, starting after the opening quotes,
+/// returning the position after the closing ones. In an interpolated raw
+/// string, as many braces as there were dollar signs open a hole.
+fn raw_string(text: &str, start: usize, quotes: usize, dollars: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = start;
+    loop {
+        match *unwrap_or_return!(bytes.get(i), None) {
+            b'"' => {
+                let run = run_length(bytes, i, b'"');
+                if run >= quotes {
+                    return Some(i + run);
+                }
+                i += run;
+            },
+            b'{' if dollars > 0 => {
+                // Braces beyond those opening the hole are part of the text.
+                let run = run_length(bytes, i, b'{');
+                if run >= dollars {
+                    i = unwrap_or_return!(hole(text, i + run, dollars), None);
+                } else {
+                    i += run;
+                }
+            },
+            _ => i += 1,
+        }
+    }
+}
+
+/// Skip over the expression in a hole of an interpolated string, starting
+/// after the opening braces, returning the position after the given number of
+/// closing ones.
+fn hole(text: &str, start: usize, braces: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = start;
+    let mut depth = 0;
+    loop {
+        match *unwrap_or_return!(bytes.get(i), None) {
+            b'{' => {
+                depth += 1;
+                i += 1;
+            },
+            b'}' if depth == 0 => {
+                return if run_length(bytes, i, b'}') >= braces { Some(i + braces) } else { None };
+            },
+            b'}' => {
+                depth -= 1;
+                i += 1;
+            },
+            b'"' | b'@' | b'$' | b'\'' => {
+                let rest = &text[i..];
+                i += string_literal(rest).or_else(|| char_literal(rest)).unwrap_or(1);
+            },
+            _ => i += 1,
+        }
+    }
+}
+
+/// The length of the character literal starting the text, if there is one.
+fn char_literal(text: &str) -> Option<usize> {
+    let re = regex!(r"^'([^'\\\r\n]|\\x[0-9A-Fa-f]{1,4}|\\u[0-9A-Fa-f]{4}|\\U[0-9A-Fa-f]{8}|\\[^\r\n])'");
+    re.find(text).map(|(_, end)| end)
+}
+
+/// The number of times the byte repeats from the given position.
+fn run_length(bytes: &[u8], start: usize, byte: u8) -> usize {
+    bytes[start..].iter().take_while(|&&b| b == byte).count()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::TokenClass::{Whitespace, Newline, Comment, BlockBegin, BlockEnd, IdentifierOrKeyword};
+    use super::TokenClass::{Whitespace, Newline, Comment, StringLiteral, BlockBegin, BlockEnd, Semicolon, Keyword,
+                            Identifier};
     use super::TokenClass;
+    use super::{TokenStream, Level, Context};
     use lexer::Token::{Matched, Unmatched};
-    use lexer::Token;
+    use lexer::{Token, Span};
+    use backend::EdgeKind;
+    use backend::EdgeKind::{Inheritance, Implementation, Contains, Constraint, Field, Parameter, Instantiation, StaticAccess, Reference};
+    use backend::TypeKind;
+    use backend::TypeKind::{Class, Struct, Interface, Enum, Record, RecordStruct, Delegate};
+    use std::collections::{HashMap, HashSet};
+    use std::path::posix::Path;
+
+    fn parse_sources(sources: &[&str]) -> super::DeclMap {
+        let lexer = super::build_csharp_lexer();
+        let mut map = HashMap::new();
+        for (i, source) in sources.iter().enumerate() {
+            let mut tokens = TokenStream::new(lexer.lex(*source), &HashSet::new());
+            let file = Path::new(format!("{}.cs", i));
+            super::add_decls(&mut map, super::parse_file(&mut tokens, &file));
+        }
+        map
+    }
+
+    fn edge_kinds(sources: &[&str]) -> Vec<(String, String, EdgeKind)> {
+        let g = super::link(&parse_sources(sources), false);
+        let mut edges = vec![];
+        g.each_edge(|_, edge| {
+            edges.push((g.node_data(edge.source()).name.clone(), g.node_data(edge.target()).name.clone(), edge.data.kind));
+            true
+        });
+        edges.sort();
+        edges
+    }
+
+    fn edge_names(sources: &[&str]) -> Vec<(String, String)> {
+        edge_kinds(sources).into_iter().map(|(a, b, _)| (a, b)).collect()
+    }
 
     #[test]
     fn test_csharp_lexer() {
         let lexer = super::build_csharp_lexer();
-        let result: Vec<(Token<TokenClass>, &str)> = lexer.lex("class Fizz\n{\nBuzz buzz;\n}").collect();
+        let result: Vec<(Token<TokenClass>, &str, Span)> = lexer.lex("class Fizz\n{\nBuzz buzz;\n}").collect();
         let kind: Vec<Token<TokenClass>> = result.iter().map(|r| r.0.clone()).collect();
-        let expected = [Matched(IdentifierOrKeyword), Matched(Whitespace), Matched(IdentifierOrKeyword),
-                        Matched(Newline), Matched(BlockBegin), Matched(Newline), Matched(IdentifierOrKeyword),
-                        Matched(Whitespace), Matched(IdentifierOrKeyword), Unmatched, Matched(Newline), Matched(BlockEnd)];
+        let expected = [Matched(Keyword), Matched(Whitespace), Matched(Identifier),
+                        Matched(Newline), Matched(BlockBegin), Matched(Newline), Matched(Identifier),
+                        Matched(Whitespace), Matched(Identifier), Matched(Semicolon), Matched(Newline), Matched(BlockEnd)];
 
         assert_eq!(&kind[], &expected[]);
     }
 
+    #[test]
+    fn test_literals() {
+        let lexer = super::build_csharp_lexer();
+        let literals = [
+            r##""""##,
+            r##""a \" { b""##,
+            r##""\\""##,
+            r##""bytes"u8"##,
+            r##"@"C:\dir\ "" {""##,
+            "@\"two\nlines }\"",
+            r##"$"{x} {{ {(a ? "}" : "{")} {y:N2} }}""##,
+            r##"$@"{x}\ "" {{""##,
+            r##"@$"{x}""##,
+            r##"$"{$"{inner}"} {'}'}""##,
+            r##""""raw " "" { """"##,
+            r##"""""a """ b"""""##,
+            "\"\"\"\n    {\n    \"\"\"",
+            r##"$$"""{ {{x}} }""""##,
+            r##"$$"""{{{x}}}""""##,
+            "'{'",
+            r"'\''",
+            r"'\\'",
+            r"'\x41'",
+            r"'\u0041'",
+            "'\"'",
+        ];
+        for literal in literals.iter() {
+            let tokens: Vec<(Token<TokenClass>, &str)> = lexer.lex(*literal).map(|(tok, text, _)| (tok, text)).collect();
+            assert_eq!(tokens, vec![(Matched(StringLiteral), *literal)]);
+        }
+
+        for comment in ["/* { class X } */", "/** several\n * lines { */", "// } \"", "/**/"].iter() {
+            let tokens: Vec<(Token<TokenClass>, &str)> = lexer.lex(*comment).map(|(tok, text, _)| (tok, text)).collect();
+            assert_eq!(tokens, vec![(Matched(Comment), *comment)]);
+        }
+
+        let tokens: Vec<(Token<TokenClass>, &str)> = lexer.lex("/* a */ b /* c */").map(|(tok, text, _)| (tok, text)).collect();
+        assert_eq!(tokens, vec![(Matched(Comment), "/* a */"), (Matched(Whitespace), " "), (Matched(Identifier), "b"),
+                                (Matched(Whitespace), " "), (Matched(Comment), "/* c */")]);
+    }
+
+    #[test]
+    fn test_braces_in_literals() {
+        let edges = edge_kinds(&[r##"namespace N {
+                                         class A {
+                                             /* class Fake { */
+                                             string s = "}";
+                                             char c = '}';
+                                             string v = @"\";
+                                             string i = $"{x} }}" + new B();
+                                             string r = """
+                                                 } "" }
+                                                 """;
+                                             C last;
+                                         }
+                                         class B {}
+                                         class C {}
+                                     }"##]);
+        let expected: Vec<(String, String, EdgeKind)> = vec![
+            ("N.A", "N.B", Instantiation),
+            ("N.A", "N.C", Field),
+        ].into_iter().map(|(a, b, k)| (a.to_string(), b.to_string(), k)).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_conditions() {
+        let defines: HashSet<String> = vec!["DEBUG".to_string(), "NET8_0".to_string()].into_iter().collect();
+        let cases = [
+            ("DEBUG", true), ("RELEASE", false), ("!RELEASE", true), ("DEBUG && RELEASE", false),
+            ("DEBUG || RELEASE", true), ("(RELEASE || NET8_0) && !TRACE", true), ("DEBUG == true", true),
+            ("RELEASE != false", false), ("!(DEBUG)", false), ("true // comment", true), ("DEBUG &&", false),
+            ("(DEBUG", false), ("DEBUG & RELEASE", false),
+        ];
+        for &(condition, expected) in cases.iter() {
+            let mut conditions = super::Conditions::new(&defines);
+            conditions.directive(&format!("#if {}", condition)[]);
+            assert!(conditions.active() == expected, "#if {}", condition);
+        }
+    }
+
+    #[test]
+    fn test_directives() {
+        let source = "#region Types
+                      #define LOCAL
+                      class A {
+                      #if DEBUG
+                          Debug d;
+                      #elif LOCAL
+                          Local l;
+                      #else
+                          Release r; } }
+                      #endif
+                      }
+                      # if !LOCAL
+                          class Hidden {}
+                      # endif
+                      #if X
+                      #if Y
+                      #else
+                          Nested n;
+                      #endif
+                      #endif
+                      #pragma warning disable CS0169
+                      class B {}
+                      #endregion";
+        let lexer = super::build_csharp_lexer();
+        let mut names = vec![];
+        for define in ["", "DEBUG"].iter() {
+            let defines: HashSet<String> = define.split(',').filter(|d| !d.is_empty()).map(|d| d.to_string()).collect();
+            let mut tokens = TokenStream::new(lexer.lex(source), &defines);
+            let decls = super::parse_file(&mut tokens, &Path::new("0.cs"));
+            let mut refs: Vec<String> = decls[0].refs.keys().map(|k| k.clone()).collect();
+            refs.sort();
+            names.push((decls.iter().map(|d| d.name.clone()).collect::<Vec<String>>(), refs));
+        }
+        assert_eq!(names, vec![
+            (vec!["A".to_string(), "B".to_string()], vec!["Local".to_string()]),
+            (vec!["A".to_string(), "B".to_string()], vec!["Debug".to_string()]),
+        ]);
+    }
+
     #[test]
     fn test_class_x() {
         let lexer = super::build_csharp_lexer();
-        let mut iter = lexer.lex("class Fizz\n{\nBuzz buzz}\n}");
+        let mut iter = TokenStream::new(lexer.lex("class Fizz\n{\nBuzz buzz}\n}"), &HashSet::new());
         iter.next(); // consume "class"
-        match super::class_x(&mut iter) {
-            Some((classname, set)) => {
-                assert_eq!(classname, "Fizz");
-                assert!(set.contains("Buzz"));
-                assert!(set.contains("buzz"));
+        let ctx = Context { scope: vec![Level::new(String::new(), None)], outer: vec![], file: Path::new("test.cs") };
+        match super::class_x(&mut iter, Class, &ctx, &mut vec![]) {
+            Some(decl) => {
+                assert_eq!(decl.name, "Fizz");
+                assert!(decl.refs.contains_key("Buzz"));
+                assert!(decl.refs.contains_key("buzz"));
             },
             None => {
                 panic!("Test failed.");
             },
         }
     }
+
+    #[test]
+    fn test_namespaces() {
+        let map = parse_sources(&["namespace A.B { namespace C { class X {} } class Y {} }",
+                                  "namespace D; class Z {}",
+                                  "class W {}"]);
+        let mut names: Vec<String> = map.keys().map(|k| k.clone()).collect();
+        names.sort();
+        let expected: Vec<String> = vec!["A.B.C.X", "A.B.Y", "D.Z", "W"].into_iter().map(|s| s.to_string()).collect();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn test_resolution_follows_scope() {
+        let edges = edge_names(&[
+            "namespace Core { class Logger {} }",
+            "namespace Web { class Logger {} class Page { Logger log; } }",
+            "using Core; namespace Jobs { class Job { Logger log; } }",
+            "using L = Core.Logger; namespace Tasks { class Task { L log; } }",
+            "namespace Other { class Thing { Logger log; Core.Logger log2; } }",
+        ]);
+        let expected: Vec<(String, String)> = vec![
+            ("Jobs.Job", "Core.Logger"),
+            ("Other.Thing", "Core.Logger"),
+            ("Tasks.Task", "Core.Logger"),
+            ("Web.Page", "Web.Logger"),
+        ].into_iter().map(|(a, b)| (a.to_string(), b.to_string())).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_usings_import_types_only() {
+        let edges = edge_names(&[
+            "namespace A.B { class C {} }",
+            "namespace A { class Outer { class Inner {} } }",
+            "using A; namespace X { class User { B.C c; Outer.Inner inner; } }",
+        ]);
+        let expected: Vec<(String, String)> = vec![
+            ("A.Outer", "A.Outer.Inner"),
+            ("X.User", "A.Outer.Inner"),
+        ].into_iter().map(|(a, b)| (a.to_string(), b.to_string())).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_resolution_in_dotted_namespaces() {
+        let edges = edge_names(&[
+            "namespace A { class Y {} }",
+            "namespace A.B { class X { Y y; Z z; } class Z {} }",
+            "namespace Company.Product.Models { class Foo {} }",
+            "namespace Company.Product.Data; class Store { Models.Foo foo; }",
+        ]);
+        let expected: Vec<(String, String)> = vec![
+            ("A.B.X", "A.B.Z"),
+            ("A.B.X", "A.Y"),
+            ("Company.Product.Data.Store", "Company.Product.Models.Foo"),
+        ].into_iter().map(|(a, b)| (a.to_string(), b.to_string())).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_edge_kinds() {
+        let edges = edge_kinds(&[
+            "class Base {} interface IThing {} class Part {} class Arg {} class Made {} class Helper {} class Util {} class Other {}",
+            "class Derived : Base, IThing {
+                 [Other] private Part part;
+                 public Arg Args { get; set; }
+                 public Made Build(Arg arg) {
+                     Util.Log(part);
+                     Other o;
+                     var h = new Helper();
+                     return new Made();
+                 }
+             }",
+        ]);
+        let expected: Vec<(String, String, EdgeKind)> = vec![
+            ("Derived", "Arg", Field),
+            ("Derived", "Base", Inheritance),
+            ("Derived", "Helper", Instantiation),
+            ("Derived", "IThing", Implementation),
+            ("Derived", "Made", Parameter),
+            ("Derived", "Other", Reference),
+            ("Derived", "Part", Field),
+            ("Derived", "Util", StaticAccess),
+        ].into_iter().map(|(a, b, k)| (a.to_string(), b.to_string(), k)).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_type_kinds() {
+        let map = parse_sources(&["interface IShape { double Area(); }
+                                   enum Color : byte { Red = 1, Green }
+                                   record Point(int X, int Y);
+                                   record struct Size(Point Corner) : IShape { public double Area() { return 0; } }
+                                   delegate Color Picker(Point at);
+                                   struct Pixel : IShape { Color color; }
+                                   class Circle : IShape {}"]);
+        let mut kinds: Vec<(String, TypeKind)> = map.iter().map(|(name, decls)| (name.clone(), decls[0].kind)).collect();
+        kinds.sort_by(|a, b| a.0.cmp(&b.0));
+        let expected: Vec<(String, TypeKind)> = vec![
+            ("Circle", Class), ("Color", Enum), ("IShape", Interface), ("Picker", Delegate),
+            ("Pixel", Struct), ("Point", Record), ("Size", RecordStruct),
+        ].into_iter().map(|(a, k)| (a.to_string(), k)).collect();
+        assert_eq!(kinds, expected);
+
+        let edges = edge_kinds(&["interface IBase {} interface IDerived : IBase {} class Base {}
+                                  class Impl : Base, IDerived {}"]);
+        let expected: Vec<(String, String, EdgeKind)> = vec![
+            ("IDerived", "IBase", Inheritance),
+            ("Impl", "Base", Inheritance),
+            ("Impl", "IDerived", Implementation),
+        ].into_iter().map(|(a, b, k)| (a.to_string(), b.to_string(), k)).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_generics() {
+        let edges = edge_kinds(&["class Result {} class Result<T> {} interface IEntity {} class Base<T> {} class Entity {}
+                                  class Repository<T> : Base<Entity> where T : IEntity, new() {
+                                      Result<T> Get() { return a < b ? null : null; }
+                                      Result plain;
+                                      T item;
+                                  }"]);
+        let expected: Vec<(String, String, EdgeKind)> = vec![
+            ("Repository<T>", "Base<T>", Inheritance),
+            ("Repository<T>", "Entity", Reference),
+            ("Repository<T>", "IEntity", Constraint),
+            ("Repository<T>", "Result", Field),
+            ("Repository<T>", "Result<T>", Parameter),
+        ].into_iter().map(|(a, b, k)| (a.to_string(), b.to_string(), k)).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_method_type_params() {
+        let edges = edge_kinds(&["class T {} interface IFoo {}
+                                  class Repo {
+                                      T Get<T>(T x) where T : IFoo { return default(T); }
+                                      T Make() { return null; }
+                                  }"]);
+        let expected: Vec<(String, String, EdgeKind)> = vec![
+            ("Repo", "IFoo", Parameter),
+            ("Repo", "T", Parameter),
+        ].into_iter().map(|(a, b, k)| (a.to_string(), b.to_string(), k)).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_partial_types() {
+        let map = parse_sources(&["namespace App { partial class Form { Button ok; } class Button {} }",
+                                  "using App; namespace App { partial class Form { Label title; } class Label {} }"]);
+        let g = super::link(&map, false);
+        let form = g.all_nodes().iter().find(|node| &node.data.name[] == "App.Form").unwrap();
+        let files: Vec<Path> = form.data.locations.iter().map(|location| location.file.clone()).collect();
+        assert_eq!(files, vec![Path::new("0.cs"), Path::new("1.cs")]);
+
+        let edges = edge_names(&["namespace App { partial class Form { Button ok; } class Button {} }",
+                                 "namespace App { partial class Form { Label title; } class Label {} }"]);
+        let expected: Vec<(String, String)> = vec![
+            ("App.Form", "App.Button"),
+            ("App.Form", "App.Label"),
+        ].into_iter().map(|(a, b)| (a.to_string(), b.to_string())).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_nested_types() {
+        let edges = edge_kinds(&["namespace N {
+                                      class Outer<T> {
+                                          Inner first;
+                                          class Inner { Sibling next; T value; }
+                                          enum Sibling { A }
+                                          public void Run() { Other.Go(); }
+                                      }
+                                      class Other { Outer<int>.Inner inner; }
+                                  }"]);
+        let expected: Vec<(String, String, EdgeKind)> = vec![
+            ("N.Other", "N.Outer<T>.Inner", Field),
+            ("N.Outer<T>", "N.Other", StaticAccess),
+            ("N.Outer<T>", "N.Outer<T>.Inner", Contains),
+            ("N.Outer<T>", "N.Outer<T>.Sibling", Contains),
+            ("N.Outer<T>.Inner", "N.Outer<T>.Sibling", Field),
+        ].into_iter().map(|(a, b, k)| (a.to_string(), b.to_string(), k)).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_verbatim_identifiers() {
+        let edges = edge_names(&["namespace @N { class @Foo {} class @class {} class User { @Foo foo; N.@class c; } }"]);
+        let expected: Vec<(String, String)> = vec![
+            ("N.User", "N.Foo"),
+            ("N.User", "N.class"),
+        ].into_iter().map(|(a, b)| (a.to_string(), b.to_string())).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_record_as_a_name() {
+        let edges = edge_kinds(&["class Record {} class Item {}
+                                  class Holder {
+                                      private Record record;
+                                      Item item;
+                                      public partial record Entry {}
+                                      Record Current { get; set; }
+                                  }"]);
+        let expected: Vec<(String, String, EdgeKind)> = vec![
+            ("Holder", "Holder.Entry", Contains),
+            ("Holder", "Item", Field),
+            ("Holder", "Record", Field),
+        ].into_iter().map(|(a, b, k)| (a.to_string(), b.to_string(), k)).collect();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_keywords_are_not_references() {
+        let map = parse_sources(&["class Job {
+                                       public static int Count;
+                                       public async Task Run() {
+                                           var item = await this.Load();
+                                           dynamic d = this.Load().Value;
+                                       }
+                                   }"]);
+        let refs = &map["Job".to_string()][0].refs;
+        assert!(refs.contains_key("Task"));
+        for word in ["public", "static", "int", "async", "var", "await", "this", "Load", "dynamic", "Value"].iter() {
+            assert!(!refs.contains_key(*word), "{} taken as a reference", word);
+        }
+
+        let sources = ["class Task {} class Job { Task task; System.String name; Helper h; } class Helper {}"];
+        let g = super::link(&parse_sources(&sources), true);
+        let mut edges = vec![];
+        g.each_edge(|_, edge| {
+            edges.push((g.node_data(edge.source()).name.clone(), g.node_data(edge.target()).name.clone()));
+            true
+        });
+        assert_eq!(edges, vec![("Job".to_string(), "Helper".to_string())]);
+        assert_eq!(edge_names(&sources), vec![("Job".to_string(), "Helper".to_string()),
+                                              ("Job".to_string(), "Task".to_string())]);
+    }
+
+    #[test]
+    fn test_locations() {
+        let map = parse_sources(&["namespace N {\n    class A : B {\n        C c;\n        C d;\n    }\n}",
+                                  "namespace N {\n    partial class B {}\n    class C {}\n}",
+                                  "namespace N { partial class B { } }"]);
+        let g = super::link(&map, false);
+        let mut nodes = vec![];
+        g.each_node(|_, node| {
+            let locations: Vec<(String, Span)> = node.data.locations.iter()
+                .map(|location| (location.file.as_str().unwrap().to_string(), location.span))
+                .collect();
+            nodes.push((node.data.name.clone(), locations));
+            true
+        });
+        assert_eq!(nodes, vec![
+            ("N.A".to_string(), vec![("0.cs".to_string(), Span { start: 24, end: 63, line: 2, column: 11 })]),
+            ("N.B".to_string(), vec![("1.cs".to_string(), Span { start: 32, end: 36, line: 2, column: 19 }),
+                                     ("2.cs".to_string(), Span { start: 28, end: 33, line: 1, column: 29 })]),
+            ("N.C".to_string(), vec![("1.cs".to_string(), Span { start: 47, end: 51, line: 3, column: 11 })]),
+        ]);
+
+        let mut edges = vec![];
+        g.each_edge(|_, edge| {
+            edges.push((g.node_data(edge.target()).name.clone(), edge.data.location.span));
+            true
+        });
+        assert_eq!(edges, vec![
+            ("N.B".to_string(), Span { start: 28, end: 29, line: 2, column: 15 }),
+            ("N.C".to_string(), Span { start: 40, end: 41, line: 3, column: 9 }),
+        ]);
+    }
+
+    #[test]
+    fn test_projects() {
+        let projects = [Path::new("src/App/App.csproj"), Path::new("src/App/Tests/Tests.csproj"), Path::new("Lib.csproj")];
+        let cases = [("src/App/Main.cs", Some("App")), ("src/App/Tests/Unit/Test.cs", Some("Tests")),
+                     ("src/Other.cs", Some("Lib")), ("../Outside.cs", None)];
+        for &(file, expected) in cases.iter() {
+            let project = super::project_of(&Path::new(file), &projects);
+            assert_eq!(project, expected.map(|p| p.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_configuration_defines() {
+        let project = r#"<Project>
+                           <PropertyGroup>
+                             <DefineConstants>$(DefineConstants);SHARED</DefineConstants>
+                           </PropertyGroup>
+                           <PropertyGroup Condition=" '$(Configuration)|$(Platform)' == 'Debug|AnyCPU' ">
+                             <DefineConstants>DEBUG;TRACE</DefineConstants>
+                           </PropertyGroup>
+                           <PropertyGroup Condition="'$(Configuration)' == 'Release'">
+                             <DefineConstants>RELEASE</DefineConstants>
+                           </PropertyGroup>
+                           <PropertyGroup />
+                           <PropertyGroup>
+                             <DefineConstants Condition="'$(Configuration)' == 'Release'">OPTIMIZED</DefineConstants>
+                           </PropertyGroup>
+                           <PropertyGroup Condition="'$(Configuration)' != 'Release'">
+                             <DefineConstants>CHECKED</DefineConstants>
+                           </PropertyGroup>
+                           <PropertyGroup Condition="'$(Configuration)' == 'DebugFast'">
+                             <DefineConstants>FAST</DefineConstants>
+                           </PropertyGroup>
+                           <PropertyGroup Condition="'$(OutputPath)' == 'bin/Release/' and '$(Configuration)' == 'Release'">
+                             <DefineConstants>OUTPUT</DefineConstants>
+                           </PropertyGroup>
+                         </Project>"#;
+        assert_eq!(super::configuration_defines(project, "Debug").connect(";"), "SHARED;DEBUG;TRACE;CHECKED;OUTPUT");
+        assert_eq!(super::configuration_defines(project, "release").connect(";"), "SHARED;RELEASE;OPTIMIZED;OUTPUT");
+        assert_eq!(super::configuration_defines(project, "DebugFast").connect(";"), "SHARED;CHECKED;FAST;OUTPUT");
+    }
 }
@@ -0,0 +1,133 @@
+//! Dependency cycles: groups of types that all depend on one another, directly
+//! or through each other, so that none can be understood or changed alone.
+
+use std::io::IoResult;
+use std::collections::HashMap;
+use graph::{Graph, NodeIndex, EdgeIndex};
+use backend::{GraphInfo, TypeNode, EdgeInfo};
+
+/// The types of a strongly connected component with more than one of them,
+/// and the edges between them. Every one of those edges is part of a loop.
+pub struct Cycle {
+    pub types: Vec<NodeIndex>,
+    pub edges: Vec<EdgeIndex>,
+}
+
+/// The cycles in the graph, ordered by their first type. A type referring to
+/// itself doesn't count as a cycle.
+pub fn find_cycles(graph: &Graph<TypeNode, EdgeInfo>) -> Vec<Cycle> {
+    let mut cycles: Vec<Cycle> = graph.strongly_connected_components().into_iter()
+        .filter(|types| types.len() > 1)
+        .map(|types| Cycle { types: types, edges: vec![] })
+        .collect();
+    cycles.sort_by(|a, b| a.types[0].node_id().cmp(&b.types[0].node_id()));
+
+    let mut component = HashMap::new();
+    for (i, cycle) in cycles.iter().enumerate() {
+        for node in cycle.types.iter() {
+            component.insert(node.node_id(), i);
+        }
+    }
+    for (i, edge) in graph.all_edges().iter().enumerate() {
+        let source = component.get(&edge.source().node_id()).map(|c| *c);
+        let target = component.get(&edge.target().node_id()).map(|c| *c);
+        match (source, target) {
+            (Some(source), Some(target)) if source == target => cycles[source].edges.push(EdgeIndex(i)),
+            _ => {},
+        }
+    }
+
+    cycles
+}
+
+/// Write the cycles as text: the types in each, then the edges between them
+/// with where each is found.
+pub fn report<W: Writer>(ginfo: &GraphInfo, w: &mut W) -> IoResult<()> {
+    let g = &ginfo.graph;
+    let cycles = find_cycles(g);
+    if cycles.is_empty() {
+        return writeln!(w, "No cycles.");
+    }
+
+    for (i, cycle) in cycles.iter().enumerate() {
+        if i > 0 {
+            try!(writeln!(w, ""));
+        }
+        try!(writeln!(w, "Cycle {} ({} types):", i + 1, cycle.types.len()));
+        for &node in cycle.types.iter() {
+            try!(writeln!(w, "    {}", g.node_data(node).name));
+        }
+        try!(writeln!(w, "  Edges:"));
+        for &idx in cycle.edges.iter() {
+            try!(write_edge(g, idx, w));
+        }
+    }
+    Ok(())
+}
+
+/// Write a line for an edge in a report: the types at its ends, its kind and
+/// where it is found.
+pub fn write_edge<W: Writer>(graph: &Graph<TypeNode, EdgeInfo>, idx: EdgeIndex, w: &mut W) -> IoResult<()> {
+    let edge = graph.edge(idx);
+    let location = &edge.data.location;
+    writeln!(w, "    {} -> {} ({}, {}:{}:{})",
+             graph.node_data(edge.source()).name, graph.node_data(edge.target()).name, edge.data.kind.name(),
+             location.file.display(), location.span.line, location.span.column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::Graph;
+    use backend::GraphInfo;
+    use test_support::test_graph;
+    use backend::EdgeKind::{Field, Reference};
+
+    //    A --> B --> C --> D <-> E
+    //    ^           |
+    //    +-----------+     F --> F
+    fn create_graph() -> GraphInfo {
+        test_graph(&["A", "B", "C", "D", "E", "F"],
+                   &[(0, 1, Field), (1, 2, Field), (2, 0, Reference), (2, 3, Field), (3, 4, Field), (4, 3, Reference),
+                     (5, 5, Field)])
+    }
+
+    #[test]
+    fn cycles() {
+        let ginfo = create_graph();
+        let cycles: Vec<(Vec<usize>, Vec<usize>)> = find_cycles(&ginfo.graph).iter()
+            .map(|cycle| (cycle.types.iter().map(|n| n.node_id()).collect(),
+                          cycle.edges.iter().map(|e| e.edge_id()).collect()))
+            .collect();
+        assert_eq!(cycles, vec![(vec![0, 1, 2], vec![0, 1, 2]), (vec![3, 4], vec![4, 5])]);
+    }
+
+    #[test]
+    fn reports() {
+        let mut out = vec![];
+        report(&create_graph(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+Cycle 1 (3 types):
+    A
+    B
+    C
+  Edges:
+    A -> B (field, 0.cs:1:1)
+    B -> C (field, 0.cs:2:1)
+    C -> A (reference, 0.cs:3:1)
+
+Cycle 2 (2 types):
+    D
+    E
+  Edges:
+    D -> E (field, 0.cs:5:1)
+    E -> D (reference, 0.cs:6:1)
+");
+
+        let mut out = vec![];
+        let mut ginfo = create_graph();
+        ginfo.graph = Graph::new();
+        report(&ginfo, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "No cycles.\n");
+    }
+}
@@ -0,0 +1,159 @@
+//! Focusing on a single type: the types it depends on and the types that
+//! depend on it, up to a number of edges away.
+
+use std::collections::HashMap;
+use graph::{Graph, NodeIndex, Direction, Outgoing, Incoming};
+use backend::{GraphInfo, TypeNode, EdgeInfo};
+
+/// The directions named on the command line: `out` follows edges to the
+/// types depended on, `in` to the types depending on them, `both` either way.
+pub fn directions_from_name(name: &str) -> Option<Vec<Direction>> {
+    match name {
+        "out" => Some(vec![Outgoing]),
+        "in" => Some(vec![Incoming]),
+        "both" => Some(vec![Outgoing, Incoming]),
+        _ => None,
+    }
+}
+
+/// The types with the given name, which may leave out the namespace and the
+/// types it is nested in, e.g. `Inner` for `Ns.Outer.Inner`, and the type
+/// arguments, or give their number, e.g. `Outer.Inner` or ``Outer`1.Inner``
+/// for `Outer<T>.Inner`.
+pub fn find_types(graph: &Graph<TypeNode, EdgeInfo>, name: &str) -> Vec<NodeIndex> {
+    let spellings: Vec<Vec<String>> = graph.all_nodes().iter().map(|node| spellings(&node.data.name[])).collect();
+    let exact: Vec<NodeIndex> = range(0, spellings.len())
+        .filter(|&i| spellings[i].iter().any(|spelling| &spelling[] == name))
+        .map(|i| NodeIndex(i))
+        .collect();
+    if !exact.is_empty() {
+        return exact;
+    }
+
+    let suffix = format!(".{}", name);
+    range(0, spellings.len())
+        .filter(|&i| spellings[i].iter().any(|spelling| spelling.ends_with(&suffix[])))
+        .map(|i| NodeIndex(i))
+        .collect()
+}
+
+/// The ways a type can be named: as shown, without the type arguments, and
+/// with the number of them after a backtick.
+fn spellings(name: &str) -> Vec<String> {
+    let mut plain = String::new();
+    let mut arity = String::new();
+    let mut depth = 0;
+    let mut count = 0;
+    for c in name.chars() {
+        match c {
+            '<' => {
+                depth += 1;
+                if depth == 1 {
+                    count = 1;
+                }
+            },
+            '>' => {
+                depth -= 1;
+                if depth == 0 {
+                    arity.push_str(&format!("`{}", count)[]);
+                }
+            },
+            ',' if depth == 1 => count += 1,
+            _ if depth == 0 => {
+                plain.push(c);
+                arity.push(c);
+            },
+            _ => {},
+        }
+    }
+    vec![name.to_string(), plain, arity]
+}
+
+/// Copy of the graph with only the types reachable from `start` within
+/// `depth` edges in the given directions, and the edges between them.
+pub fn neighbourhood(ginfo: &GraphInfo, start: NodeIndex, depth: usize, dirs: &[Direction]) -> GraphInfo {
+    let mut included = vec![];
+    let mut traversal = ginfo.graph.depth_traverse_limited(start, dirs, depth);
+    while let Some(idx) = traversal.next_index() {
+        included.push(idx.node_id());
+    }
+    included.sort();
+
+    let mut graph = Graph::new();
+    let mut indices = HashMap::new();
+    for &i in included.iter() {
+        indices.insert(i, graph.add_node(ginfo.graph.node_data(NodeIndex(i)).clone()));
+    }
+    for edge in ginfo.graph.all_edges().iter() {
+        let source = indices.get(&edge.source().node_id());
+        let target = indices.get(&edge.target().node_id());
+        if let (Some(&source), Some(&target)) = (source, target) {
+            graph.add_edge(source, target, edge.data.clone());
+        }
+    }
+
+    GraphInfo { name: ginfo.name.clone(), graph: graph, legend: ginfo.legend.clone() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::NodeIndex;
+    use backend::GraphInfo;
+    use test_support::test_graph;
+    use backend::EdgeKind::Field;
+
+    //    A --> N.B --> C --> D
+    //           ^
+    //    E -----+      M.B
+    fn create_graph() -> GraphInfo {
+        test_graph(&["A", "N.B", "C", "D", "E", "M.B"], &[(0, 1, Field), (1, 2, Field), (2, 3, Field), (4, 1, Field)])
+    }
+
+    fn names(ginfo: &GraphInfo) -> Vec<String> {
+        ginfo.graph.all_nodes().iter().map(|node| node.data.name.clone()).collect()
+    }
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn find() {
+        let ginfo = create_graph();
+        assert_eq!(find_types(&ginfo.graph, "N.B"), vec![NodeIndex(1)]);
+        assert_eq!(find_types(&ginfo.graph, "B"), vec![NodeIndex(1), NodeIndex(5)]);
+        assert_eq!(find_types(&ginfo.graph, "C"), vec![NodeIndex(2)]);
+        assert_eq!(find_types(&ginfo.graph, "X"), vec![]);
+    }
+
+    #[test]
+    fn find_generic() {
+        let ginfo = test_graph(&["Result<T>", "N.Outer<K, List<V>>.Inner"], &[]);
+        assert_eq!(find_types(&ginfo.graph, "Result"), vec![NodeIndex(0)]);
+        assert_eq!(find_types(&ginfo.graph, "Result`1"), vec![NodeIndex(0)]);
+        assert_eq!(find_types(&ginfo.graph, "Result<T>"), vec![NodeIndex(0)]);
+        assert_eq!(find_types(&ginfo.graph, "Outer.Inner"), vec![NodeIndex(1)]);
+        assert_eq!(find_types(&ginfo.graph, "N.Outer`2.Inner"), vec![NodeIndex(1)]);
+        assert_eq!(find_types(&ginfo.graph, "Inner"), vec![NodeIndex(1)]);
+    }
+
+    #[test]
+    fn neighbourhoods() {
+        let ginfo = create_graph();
+        let around = |depth, direction| neighbourhood(&ginfo, NodeIndex(1), depth, &directions_from_name(direction).unwrap()[]);
+
+        assert_eq!(names(&around(1, "out")), strings(&["N.B", "C"]));
+        assert_eq!(names(&around(2, "out")), strings(&["N.B", "C", "D"]));
+        assert_eq!(names(&around(1, "in")), strings(&["A", "N.B", "E"]));
+        assert_eq!(names(&around(1, "both")), strings(&["A", "N.B", "C", "E"]));
+
+        let focused = around(1, "both");
+        let edges: Vec<(String, String)> = focused.graph.all_edges().iter()
+            .map(|edge| (focused.graph.node_data(edge.source()).name.clone(),
+                         focused.graph.node_data(edge.target()).name.clone()))
+            .collect();
+        assert_eq!(edges, vec![("A".to_string(), "N.B".to_string()), ("N.B".to_string(), "C".to_string()),
+                               ("E".to_string(), "N.B".to_string())]);
+    }
+}
@@ -14,9 +14,11 @@
 //!
 //! You customize the graph by specifying a "node data" type `N` and an
 //! "edge data" type `E`. You can then later gain access (mutable or
-//! immutable) to these "user-data" bits. Currently, you can only add
-//! nodes or edges to the graph. You cannot remove or modify them once
-//! added. This could be changed if we have a need.
+//! immutable) to these "user-data" bits. Nodes and edges can be removed
+//! too, but as indices are kept dense, the nodes or edges after a removed
+//! one are renumbered, and indices taken before the removal are stale.
+//! Removing one at a time takes time proportional to the size of the
+//! graph; `retain_nodes` and `retain_edges` remove many at once.
 //!
 //! # Implementation details
 //!
@@ -34,7 +36,11 @@
 
 use std::fmt::{Formatter, Error, Show};
 use std::uint;
-use std::collections::BitvSet;
+use std::mem;
+use std::cmp::{min, max};
+use std::collections::{BitvSet, HashMap, RingBuf};
+use std::iter::Enumerate;
+use std::slice;
 
 pub struct Graph<N,E> {
     nodes: Vec<Node<N>> ,
@@ -213,6 +219,119 @@ impl<N,E> Graph<N,E> {
         self.edges[edge.get()].next_edge[dir.repr]
     }
 
+    ///////////////////////////////////////////////////////////////////////////
+    // Removal
+
+    pub fn remove_edge(&mut self, idx: EdgeIndex) -> E {
+        //! Removes the edge `idx` and returns its data. The edges after it
+        //! move down one index.
+
+        let (source, target) = (self.edges[idx.get()].source, self.edges[idx.get()].target);
+        self.unlink(source, idx, Outgoing);
+        self.unlink(target, idx, Incoming);
+        let edge = self.edges.remove(idx.get());
+
+        let shift = |e: &mut EdgeIndex| {
+            if *e != InvalidEdgeIndex && e.get() > idx.get() {
+                *e = EdgeIndex(e.get() - 1);
+            }
+        };
+        for node in self.nodes.iter_mut() {
+            for e in node.first_edge.iter_mut() {
+                shift(e);
+            }
+        }
+        for edge in self.edges.iter_mut() {
+            for e in edge.next_edge.iter_mut() {
+                shift(e);
+            }
+        }
+        edge.data
+    }
+
+    fn unlink(&mut self, node: NodeIndex, idx: EdgeIndex, dir: Direction) {
+        //! Takes the edge `idx` out of the list of edges adjacent to `node`
+        //! in the direction `dir`
+
+        let next = self.edges[idx.get()].next_edge[dir.repr];
+        if self.nodes[node.get()].first_edge[dir.repr] == idx {
+            self.nodes[node.get()].first_edge[dir.repr] = next;
+            return;
+        }
+        let mut edge_idx = self.nodes[node.get()].first_edge[dir.repr];
+        while edge_idx != InvalidEdgeIndex {
+            let following = self.edges[edge_idx.get()].next_edge[dir.repr];
+            if following == idx {
+                self.edges[edge_idx.get()].next_edge[dir.repr] = next;
+                return;
+            }
+            edge_idx = following;
+        }
+    }
+
+    pub fn remove_node(&mut self, idx: NodeIndex) -> N {
+        //! Removes the node `idx` along with its edges, and returns its data.
+        //! The nodes after it move down one index, as do the edges after
+        //! any of its edges.
+
+        self.retain_edges(|_, edge| edge.source != idx && edge.target != idx);
+        let node = self.nodes.remove(idx.get());
+        for edge in self.edges.iter_mut() {
+            if edge.source.get() > idx.get() {
+                edge.source = NodeIndex(edge.source.get() - 1);
+            }
+            if edge.target.get() > idx.get() {
+                edge.target = NodeIndex(edge.target.get() - 1);
+            }
+        }
+        node.data
+    }
+
+    pub fn retain_edges<F>(&mut self, mut keep: F) where
+        F: FnMut(EdgeIndex, &Edge<E>) -> bool,
+    {
+        //! Removes the edges for which `keep` returns false. The remaining
+        //! edges keep their order, but are numbered afresh.
+
+        let edges = mem::replace(&mut self.edges, Vec::new());
+        for node in self.nodes.iter_mut() {
+            node.first_edge = [InvalidEdgeIndex, InvalidEdgeIndex];
+        }
+        for (i, edge) in edges.into_iter().enumerate() {
+            if keep(EdgeIndex(i), &edge) {
+                self.add_edge(edge.source, edge.target, edge.data);
+            }
+        }
+    }
+
+    pub fn retain_nodes<F>(&mut self, mut keep: F) where
+        F: FnMut(NodeIndex, &N) -> bool,
+    {
+        //! Removes the nodes for which `keep` returns false, along with their
+        //! edges. The remaining nodes and edges keep their order, but are
+        //! numbered afresh.
+
+        let nodes = mem::replace(&mut self.nodes, Vec::new());
+        let mut indices = vec![];
+        for (i, mut node) in nodes.into_iter().enumerate() {
+            if keep(NodeIndex(i), &node.data) {
+                indices.push(NodeIndex(self.nodes.len()));
+                node.first_edge = [InvalidEdgeIndex, InvalidEdgeIndex];
+                self.nodes.push(node);
+            } else {
+                indices.push(InvalidNodeIndex);
+            }
+        }
+
+        let edges = mem::replace(&mut self.edges, Vec::new());
+        for edge in edges.into_iter() {
+            let (source, target) = (indices[edge.source.get()], indices[edge.target.get()]);
+            if source != InvalidNodeIndex && target != InvalidNodeIndex {
+                self.add_edge(source, target, edge.data);
+            }
+        }
+    }
+
     ///////////////////////////////////////////////////////////////////////////
     // Iterating over nodes, edges
 
@@ -267,6 +386,37 @@ impl<N,E> Graph<N,E> {
         return true;
     }
 
+    pub fn degree(&self, node: NodeIndex, dir: Direction) -> uint {
+        //! Counts the edges adjacent to the node `node` in the direction `dir`
+
+        self.adjacent_edges(node, dir).count()
+    }
+
+    ///////////////////////////////////////////////////////////////////////////
+    // Iterators over nodes, edges
+
+    pub fn nodes<'a>(&'a self) -> Nodes<'a, N> {
+        //! Iterates over all nodes defined in the graph, with their indices
+        Nodes { iter: self.nodes.iter().enumerate() }
+    }
+
+    pub fn edges<'a>(&'a self) -> Edges<'a, E> {
+        //! Iterates over all edges defined in the graph, with their indices
+        Edges { iter: self.edges.iter().enumerate() }
+    }
+
+    pub fn adjacent_edges<'a>(&'a self, node: NodeIndex, dir: Direction) -> AdjacentEdges<'a, N, E> {
+        //! Iterates over all edges adjacent to the node `node` in the
+        //! direction `dir`, in the same order as `each_adjacent_edge`
+        AdjacentEdges { graph: self, dir: dir, next: self.first_adjacent(node, dir) }
+    }
+
+    pub fn neighbors<'a>(&'a self, node: NodeIndex, dir: Direction) -> Neighbors<'a, N, E> {
+        //! Iterates over the nodes at the other end of the edges adjacent to
+        //! the node `node` in the direction `dir`, once for each edge
+        Neighbors { edges: self.adjacent_edges(node, dir) }
+    }
+
     ///////////////////////////////////////////////////////////////////////////
     // Fixed-point iteration
     //
@@ -290,40 +440,464 @@ impl<N,E> Graph<N,E> {
         }
     }
 
+    ///////////////////////////////////////////////////////////////////////////
+    // Strongly connected components
+
+    pub fn strongly_connected_components(&self) -> Vec<Vec<NodeIndex>> {
+        //! Partitions the nodes into strongly connected components, each the
+        //! nodes that can all reach one another by outgoing edges, using
+        //! Tarjan's algorithm. Each component comes before any component with
+        //! edges into it, and lists its nodes in index order.
+
+        let n = self.nodes.len();
+        let mut index: Vec<uint> = range(0, n).map(|_| uint::MAX).collect();
+        let mut lowlink: Vec<uint> = range(0, n).map(|_| uint::MAX).collect();
+        let mut on_stack: Vec<bool> = range(0, n).map(|_| false).collect();
+        let mut stack = vec![];
+        let mut components = vec![];
+        let mut next = 0;
+
+        for root in range(0, n) {
+            if index[root] != uint::MAX {
+                continue;
+            }
+
+            // Recursion is done with an explicit stack of the nodes being
+            // visited and the next edge to follow out of each, as graphs can
+            // be deep enough to overflow the real one.
+            let mut calls = vec![(root, self.first_adjacent(NodeIndex(root), Outgoing))];
+            index[root] = next;
+            lowlink[root] = next;
+            next += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while !calls.is_empty() {
+                let last = calls.len() - 1;
+                let (v, edge) = calls[last];
+                if edge != InvalidEdgeIndex {
+                    calls[last].1 = self.next_adjacent(edge, Outgoing);
+                    let w = self.edges[edge.get()].target.get();
+                    if index[w] == uint::MAX {
+                        index[w] = next;
+                        lowlink[w] = next;
+                        next += 1;
+                        stack.push(w);
+                        on_stack[w] = true;
+                        calls.push((w, self.first_adjacent(NodeIndex(w), Outgoing)));
+                    } else if on_stack[w] {
+                        lowlink[v] = min(lowlink[v], index[w]);
+                    }
+                    continue;
+                }
+
+                calls.pop();
+                if let Some(&(parent, _)) = calls.last() {
+                    lowlink[parent] = min(lowlink[parent], lowlink[v]);
+                }
+                if lowlink[v] == index[v] {
+                    let mut component = vec![];
+                    loop {
+                        let w = stack.pop().unwrap();
+                        on_stack[w] = false;
+                        component.push(w);
+                        if w == v {
+                            break;
+                        }
+                    }
+                    component.sort();
+                    components.push(component.into_iter().map(|i| NodeIndex(i)).collect());
+                }
+            }
+        }
+
+        components
+    }
+
+    pub fn topological_layers(&self) -> Vec<uint> {
+        //! Assigns each node a layer, so that every edge leads down to a lower
+        //! one: 0 for nodes without edges to other nodes, otherwise one more
+        //! than the highest layer their edges lead to. The nodes of a strongly
+        //! connected component are taken together and share a layer, which
+        //! leaves the edges between them as the only ones not leading down.
+
+        let components = self.strongly_connected_components();
+        let mut component: Vec<uint> = range(0, self.nodes.len()).map(|_| 0).collect();
+        for (c, nodes) in components.iter().enumerate() {
+            for node in nodes.iter() {
+                component[node.get()] = c;
+            }
+        }
+
+        // Components come before any with edges into them, so the layers
+        // their edges lead to are known by the time each is reached.
+        let mut component_layers: Vec<uint> = vec![];
+        for (c, nodes) in components.iter().enumerate() {
+            let mut layer = 0;
+            for &node in nodes.iter() {
+                self.each_outgoing_edge(node, |_, edge| {
+                    let target = component[edge.target.get()];
+                    if target != c {
+                        layer = max(layer, component_layers[target] + 1);
+                    }
+                    true
+                });
+            }
+            component_layers.push(layer);
+        }
+
+        component.iter().map(|&c| component_layers[c]).collect()
+    }
+
+    pub fn redundant_edges(&self) -> Vec<EdgeIndex> {
+        //! Finds the edges implied by longer paths, which the transitive
+        //! reduction of the graph leaves out. Each strongly connected
+        //! component is taken as a single node, so edges within one, and
+        //! edges between two that nothing else connects, are never redundant.
+
+        let components = self.strongly_connected_components();
+        let mut component: Vec<uint> = range(0, self.nodes.len()).map(|_| 0).collect();
+        for (c, nodes) in components.iter().enumerate() {
+            for node in nodes.iter() {
+                component[node.get()] = c;
+            }
+        }
+
+        // The components each one has edges to, then those reachable from
+        // it through some other component. Components come before any with
+        // edges into them, so those they have edges to are done first.
+        let mut successors: Vec<BitvSet> = components.iter().map(|_| BitvSet::new()).collect();
+        for edge in self.edges.iter() {
+            let (source, target) = (component[edge.source.get()], component[edge.target.get()]);
+            if source != target {
+                successors[source].insert(target);
+            }
+        }
+        let mut indirect: Vec<BitvSet> = vec![];
+        for c in range(0, components.len()) {
+            let mut reachable = BitvSet::new();
+            for s in successors[c].iter() {
+                reachable.union_with(&successors[s]);
+                reachable.union_with(&indirect[s]);
+            }
+            indirect.push(reachable);
+        }
+
+        let mut redundant = vec![];
+        for (i, edge) in self.edges.iter().enumerate() {
+            let (source, target) = (component[edge.source.get()], component[edge.target.get()]);
+            if source != target && indirect[source].contains(&target) {
+                redundant.push(EdgeIndex(i));
+            }
+        }
+        redundant
+    }
+
+    pub fn breadth_traverse<'a>(&'a self, start: NodeIndex) -> BreadthFirstTraversal<'a, N, E> {
+        //! Traverses the nodes reachable from `start` by outgoing edges,
+        //! nearest first
+
+        let mut visited = BitvSet::new();
+        visited.insert(start.get());
+        let mut queue = RingBuf::new();
+        queue.push_back(start);
+        BreadthFirstTraversal { graph: self, queue: queue, visited: visited }
+    }
+
+    pub fn depth_first_events<'a>(&'a self, start: NodeIndex) -> DepthFirstEvents<'a, N, E> {
+        //! Traverses the nodes reachable from `start` by outgoing edges,
+        //! depth first, telling when each is first reached and when all the
+        //! nodes reachable from it are done. Picking out the one kind of
+        //! event or the other gives the nodes in pre-order or post-order.
+
+        DepthFirstEvents { graph: self, stack: vec![], visited: BitvSet::new(), start: Some(start) }
+    }
+
     pub fn depth_traverse<'a>(&'a self, start: NodeIndex) -> DepthFirstTraversal<'a, N, E>  {
+        self.depth_traverse_limited(start, &[Outgoing], uint::MAX)
+    }
+
+    pub fn depth_traverse_limited<'a>(&'a self,
+                                      start: NodeIndex,
+                                      dirs: &[Direction],
+                                      max_depth: uint)
+                                      -> DepthFirstTraversal<'a, N, E> {
+        //! Traverses the nodes reachable from `start` by following edges in
+        //! any of the directions `dirs`, going no more than `max_depth` edges
+        //! away from it
+
         DepthFirstTraversal {
             graph: self,
-            stack: vec![start],
-            visited: BitvSet::new()
+            stack: vec![(start, 0)],
+            visited: BitvSet::new(),
+            depths: HashMap::new(),
+            dirs: dirs.to_vec(),
+            max_depth: max_depth,
         }
     }
 }
 
 pub struct DepthFirstTraversal<'g, N:'g, E:'g> {
     graph: &'g Graph<N, E>,
-    stack: Vec<NodeIndex>,
-    visited: BitvSet
+    stack: Vec<(NodeIndex, uint)>,
+    visited: BitvSet,
+    // The fewest edges each node has been reached by so far. A node first
+    // reached by a long path is expanded again when a shorter one is found,
+    // so that the depth limit doesn't hide nodes close to it. Without a limit
+    // each node is expanded only once.
+    depths: HashMap<uint, uint>,
+    dirs: Vec<Direction>,
+    max_depth: uint,
+}
+
+impl<'g, N, E> DepthFirstTraversal<'g, N, E> {
+    pub fn next_index(&mut self) -> Option<NodeIndex> {
+        //! Like `next`, but gives the index of the node rather than its data
+
+        let graph = self.graph;
+        let unlimited = self.max_depth == uint::MAX;
+        while let Some((idx, depth)) = self.stack.pop() {
+            match self.depths.get(&idx.node_id()) {
+                Some(&seen) if unlimited || seen <= depth => continue,
+                _ => {},
+            }
+            self.depths.insert(idx.node_id(), depth);
+
+            if depth < self.max_depth {
+                let mut adjacent = vec![];
+                for &dir in self.dirs.iter() {
+                    adjacent.extend(graph.neighbors(idx, dir));
+                }
+                for next in adjacent.into_iter() {
+                    match self.depths.get(&next.node_id()) {
+                        Some(&seen) if unlimited || seen <= depth + 1 => {},
+                        _ => self.stack.push((next, depth + 1)),
+                    }
+                }
+            }
+
+            if self.visited.insert(idx.node_id()) {
+                return Some(idx);
+            }
+        }
+
+        return None;
+    }
 }
 
 impl<'g, N, E> Iterator for DepthFirstTraversal<'g, N, E> {
     type Item = &'g N;
 
     fn next(&mut self) -> Option<&'g N> {
-        while let Some(idx) = self.stack.pop() {
-            if !self.visited.insert(idx.node_id()) {
-                continue;
+        let graph = self.graph;
+        self.next_index().map(|idx| graph.node_data(idx))
+    }
+}
+
+/// A graph whose nodes can be looked up by a key, such as the name of what
+/// they stand for, and with at most one edge from one node to another.
+pub struct IndexedGraph<N,E> {
+    graph: Graph<N,E>,
+    nodes: HashMap<String, NodeIndex>,
+    edges: HashMap<(uint, uint), EdgeIndex>,
+}
+
+impl<N,E> IndexedGraph<N,E> {
+    pub fn new() -> IndexedGraph<N,E> {
+        IndexedGraph {
+            graph: Graph::new(),
+            nodes: HashMap::new(),
+            edges: HashMap::new(),
+        }
+    }
+
+    pub fn graph<'a>(&'a self) -> &'a Graph<N,E> {
+        &self.graph
+    }
+
+    pub fn into_graph(self) -> Graph<N,E> {
+        self.graph
+    }
+
+    pub fn find_node(&self, key: &str) -> Option<NodeIndex> {
+        self.nodes.get(key).map(|idx| *idx)
+    }
+
+    pub fn get_or_add_node<F>(&mut self, key: &str, make: F) -> NodeIndex where
+        F: FnOnce() -> N,
+    {
+        //! Finds the node with the key `key`, adding one with the data from
+        //! `make` if there isn't one yet
+
+        if let Some(&idx) = self.nodes.get(key) {
+            return idx;
+        }
+        let idx = self.graph.add_node(make());
+        self.nodes.insert(key.to_string(), idx);
+        idx
+    }
+
+    pub fn add_node(&mut self, data: N) -> NodeIndex {
+        //! Adds a node without a key, which can't be looked up
+        self.graph.add_node(data)
+    }
+
+    pub fn mut_node_data<'a>(&'a mut self, idx: NodeIndex) -> &'a mut N {
+        self.graph.mut_node_data(idx)
+    }
+
+    pub fn find_edge(&self, source: NodeIndex, target: NodeIndex) -> Option<EdgeIndex> {
+        self.edges.get(&(source.get(), target.get())).map(|idx| *idx)
+    }
+
+    pub fn add_or_update_edge<F>(&mut self, source: NodeIndex, target: NodeIndex, data: E, merge: F) -> EdgeIndex where
+        F: FnOnce(&mut E, E),
+    {
+        //! Adds an edge from `source` to `target`, or if there is one already,
+        //! has `merge` fold `data` into its data instead
+
+        let key = (source.get(), target.get());
+        if let Some(&idx) = self.edges.get(&key) {
+            merge(self.graph.mut_edge_data(idx), data);
+            return idx;
+        }
+        let idx = self.graph.add_edge(source, target, data);
+        self.edges.insert(key, idx);
+        idx
+    }
+}
+
+pub struct Nodes<'g, N:'g> {
+    iter: Enumerate<slice::Iter<'g, Node<N>>>,
+}
+
+impl<'g, N> Iterator for Nodes<'g, N> {
+    type Item = (NodeIndex, &'g Node<N>);
+
+    fn next(&mut self) -> Option<(NodeIndex, &'g Node<N>)> {
+        self.iter.next().map(|(i, node)| (NodeIndex(i), node))
+    }
+}
+
+pub struct Edges<'g, E:'g> {
+    iter: Enumerate<slice::Iter<'g, Edge<E>>>,
+}
+
+impl<'g, E> Iterator for Edges<'g, E> {
+    type Item = (EdgeIndex, &'g Edge<E>);
+
+    fn next(&mut self) -> Option<(EdgeIndex, &'g Edge<E>)> {
+        self.iter.next().map(|(i, edge)| (EdgeIndex(i), edge))
+    }
+}
+
+pub struct AdjacentEdges<'g, N:'g, E:'g> {
+    graph: &'g Graph<N, E>,
+    dir: Direction,
+    next: EdgeIndex,
+}
+
+impl<'g, N, E> Iterator for AdjacentEdges<'g, N, E> {
+    type Item = (EdgeIndex, &'g Edge<E>);
+
+    fn next(&mut self) -> Option<(EdgeIndex, &'g Edge<E>)> {
+        if self.next == InvalidEdgeIndex {
+            return None;
+        }
+        let idx = self.next;
+        let edge = self.graph.edge(idx);
+        self.next = edge.next_edge[self.dir.repr];
+        Some((idx, edge))
+    }
+}
+
+pub struct Neighbors<'g, N:'g, E:'g> {
+    edges: AdjacentEdges<'g, N, E>,
+}
+
+impl<'g, N, E> Iterator for Neighbors<'g, N, E> {
+    type Item = NodeIndex;
+
+    fn next(&mut self) -> Option<NodeIndex> {
+        let outgoing = self.edges.dir.repr == Outgoing.repr;
+        self.edges.next().map(|(_, edge)| if outgoing { edge.target } else { edge.source })
+    }
+}
+
+pub struct BreadthFirstTraversal<'g, N:'g, E:'g> {
+    graph: &'g Graph<N, E>,
+    queue: RingBuf<NodeIndex>,
+    visited: BitvSet,
+}
+
+impl<'g, N, E> Iterator for BreadthFirstTraversal<'g, N, E> {
+    type Item = NodeIndex;
+
+    fn next(&mut self) -> Option<NodeIndex> {
+        let idx = match self.queue.pop_front() {
+            Some(idx) => idx,
+            None => return None,
+        };
+        for next in self.graph.neighbors(idx, Outgoing) {
+            if self.visited.insert(next.get()) {
+                self.queue.push_back(next);
             }
-            self.graph.each_outgoing_edge(idx, |_, e| -> bool {
-                if !self.visited.contains(&e.target().node_id()) {
-                    self.stack.push(e.target());
-                }
-                true
-            });
+        }
+        Some(idx)
+    }
+}
+
+/// What happens to a node in a depth first traversal.
+#[derive(Clone, Copy, PartialEq, Show)]
+pub enum TraversalEvent {
+    /// The node is reached for the first time.
+    Discover(NodeIndex),
+    /// Everything reachable from the node has been discovered.
+    Finish(NodeIndex),
+}
+
+pub struct DepthFirstEvents<'g, N:'g, E:'g> {
+    graph: &'g Graph<N, E>,
+    // The nodes being visited, each with the next of its outgoing edges to
+    // follow.
+    stack: Vec<(NodeIndex, EdgeIndex)>,
+    visited: BitvSet,
+    // The node to start from, until it is discovered.
+    start: Option<NodeIndex>,
+}
+
+impl<'g, N, E> DepthFirstEvents<'g, N, E> {
+    fn discover(&mut self, idx: NodeIndex) -> TraversalEvent {
+        self.visited.insert(idx.get());
+        self.stack.push((idx, self.graph.first_adjacent(idx, Outgoing)));
+        TraversalEvent::Discover(idx)
+    }
+}
 
-            return Some(self.graph.node_data(idx));
+impl<'g, N, E> Iterator for DepthFirstEvents<'g, N, E> {
+    type Item = TraversalEvent;
+
+    fn next(&mut self) -> Option<TraversalEvent> {
+        if let Some(start) = self.start.take() {
+            return Some(self.discover(start));
         }
 
-        return None;
+        loop {
+            let last = match self.stack.len() {
+                0 => return None,
+                len => len - 1,
+            };
+            let (idx, edge) = self.stack[last];
+            if edge == InvalidEdgeIndex {
+                self.stack.pop();
+                return Some(TraversalEvent::Finish(idx));
+            }
+            self.stack[last].1 = self.graph.next_adjacent(edge, Outgoing);
+            let target = self.graph.edge(edge).target;
+            if !self.visited.contains(&target.get()) {
+                return Some(self.discover(target));
+            }
+        }
     }
 }
 
@@ -483,4 +1057,284 @@ mod test {
                             &[("BD", "B")],
                             &[("DE", "E")]);
     }
+
+    fn edge_names(graph: &TestGraph) -> Vec<&'static str> {
+        graph.all_edges().iter().map(|edge| edge.data).collect()
+    }
+
+    #[test]
+    fn nodes_and_edges() {
+        let graph = create_graph();
+        let nodes: Vec<(uint, &str)> = graph.nodes().map(|(i, node)| (i.get(), node.data)).collect();
+        assert_eq!(nodes, vec![(0, "A"), (1, "B"), (2, "C"), (3, "D"), (4, "E"), (5, "F")]);
+        let edges: Vec<(uint, &str)> = graph.edges().map(|(i, edge)| (i.get(), edge.data)).collect();
+        assert_eq!(edges, vec![(0, "AB"), (1, "BC"), (2, "BD"), (3, "DE"), (4, "EC"), (5, "FB")]);
+    }
+
+    #[test]
+    fn adjacent_edges_and_neighbors() {
+        let graph = create_graph();
+        let outgoing: Vec<&str> = graph.adjacent_edges(NodeIndex(1), Outgoing).map(|(_, edge)| edge.data).collect();
+        assert_eq!(outgoing, vec!["BD", "BC"]);
+        let incoming: Vec<uint> = graph.adjacent_edges(NodeIndex(1), Incoming).map(|(i, _)| i.get()).collect();
+        assert_eq!(incoming, vec![5, 0]);
+
+        let names = |dir| -> Vec<&str> { graph.neighbors(NodeIndex(1), dir).map(|n| *graph.node_data(n)).collect() };
+        assert_eq!(names(Outgoing), vec!["D", "C"]);
+        assert_eq!(names(Incoming), vec!["F", "A"]);
+        assert_eq!(graph.neighbors(NodeIndex(2), Outgoing).count(), 0);
+    }
+
+    #[test]
+    fn breadth_traverse() {
+        let graph = create_graph();
+        let names: Vec<&str> = graph.breadth_traverse(NodeIndex(0)).map(|n| *graph.node_data(n)).collect();
+        assert_eq!(names, vec!["A", "B", "D", "C", "E"]);
+        let names: Vec<&str> = graph.breadth_traverse(NodeIndex(2)).map(|n| *graph.node_data(n)).collect();
+        assert_eq!(names, vec!["C"]);
+    }
+
+    #[test]
+    fn depth_first_events() {
+        let graph = create_graph();
+        let events: Vec<(char, &str)> = graph.depth_first_events(NodeIndex(0))
+            .map(|event| match event {
+                TraversalEvent::Discover(n) => ('+', *graph.node_data(n)),
+                TraversalEvent::Finish(n) => ('-', *graph.node_data(n)),
+            })
+            .collect();
+        assert_eq!(events, vec![('+', "A"), ('+', "B"), ('+', "D"), ('+', "E"), ('+', "C"),
+                                ('-', "C"), ('-', "E"), ('-', "D"), ('-', "B"), ('-', "A")]);
+    }
+
+    #[test]
+    fn indexed_graph() {
+        let mut graph: IndexedGraph<&'static str, uint> = IndexedGraph::new();
+        let a = graph.get_or_add_node("a", || "A");
+        let b = graph.get_or_add_node("b", || "B");
+        assert_eq!(graph.get_or_add_node("a", || panic!("added twice")), a);
+        let c = graph.add_node("C");
+        assert_eq!(graph.find_node("b"), Some(b));
+        assert_eq!(graph.find_node("c"), None);
+
+        let ab = graph.add_or_update_edge(a, b, 1, |w, more| *w += more);
+        assert_eq!(graph.add_or_update_edge(a, b, 2, |w, more| *w += more), ab);
+        let ba = graph.add_or_update_edge(b, a, 4, |w, more| *w += more);
+        graph.add_or_update_edge(a, c, 8, |w, more| *w += more);
+        assert_eq!(graph.find_edge(b, a), Some(ba));
+        assert_eq!(graph.find_edge(c, a), None);
+
+        let graph = graph.into_graph();
+        let nodes: Vec<&str> = graph.all_nodes().iter().map(|node| node.data).collect();
+        assert_eq!(nodes, vec!["A", "B", "C"]);
+        let edges: Vec<(uint, uint, uint)> = graph.all_edges().iter()
+            .map(|edge| (edge.source().get(), edge.target().get(), edge.data))
+            .collect();
+        assert_eq!(edges, vec![(0, 1, 3), (1, 0, 4), (0, 2, 8)]);
+    }
+
+    #[test]
+    fn remove_edge() {
+        let mut graph = create_graph();
+        assert_eq!(graph.remove_edge(EdgeIndex(2)), "BD");
+        assert_eq!(edge_names(&graph), vec!["AB", "BC", "DE", "EC", "FB"]);
+        test_adjacent_edges(&graph, NodeIndex(1), "B",
+                            &[("FB", "F"), ("AB", "A")],
+                            &[("BC", "C")]);
+        test_adjacent_edges(&graph, NodeIndex(3), "D",
+                            &[],
+                            &[("DE", "E")]);
+        test_adjacent_edges(&graph, NodeIndex(4), "E",
+                            &[("DE", "D")],
+                            &[("EC", "C")]);
+
+        assert_eq!(graph.remove_edge(EdgeIndex(0)), "AB");
+        test_adjacent_edges(&graph, NodeIndex(0), "A",
+                            &[],
+                            &[]);
+        test_adjacent_edges(&graph, NodeIndex(1), "B",
+                            &[("FB", "F")],
+                            &[("BC", "C")]);
+    }
+
+    #[test]
+    fn remove_node() {
+        let mut graph = create_graph();
+        assert_eq!(graph.remove_node(NodeIndex(3)), "D");
+        let nodes: Vec<&str> = graph.all_nodes().iter().map(|node| node.data).collect();
+        assert_eq!(nodes, vec!["A", "B", "C", "E", "F"]);
+        assert_eq!(edge_names(&graph), vec!["AB", "BC", "EC", "FB"]);
+        test_adjacent_edges(&graph, NodeIndex(1), "B",
+                            &[("FB", "F"), ("AB", "A")],
+                            &[("BC", "C")]);
+        test_adjacent_edges(&graph, NodeIndex(2), "C",
+                            &[("EC", "E"), ("BC", "B")],
+                            &[]);
+        test_adjacent_edges(&graph, NodeIndex(3), "E",
+                            &[],
+                            &[("EC", "C")]);
+    }
+
+    #[test]
+    fn retain_edges() {
+        let mut graph = create_graph();
+        graph.retain_edges(|_, edge| !edge.data.starts_with("B"));
+        assert_eq!(edge_names(&graph), vec!["AB", "DE", "EC", "FB"]);
+        test_adjacent_edges(&graph, NodeIndex(1), "B",
+                            &[("FB", "F"), ("AB", "A")],
+                            &[]);
+        test_adjacent_edges(&graph, NodeIndex(2), "C",
+                            &[("EC", "E")],
+                            &[]);
+    }
+
+    #[test]
+    fn retain_nodes() {
+        let mut graph = create_graph();
+        graph.retain_nodes(|_, &name| name != "B");
+        let nodes: Vec<&str> = graph.all_nodes().iter().map(|node| node.data).collect();
+        assert_eq!(nodes, vec!["A", "C", "D", "E", "F"]);
+        assert_eq!(edge_names(&graph), vec!["DE", "EC"]);
+        test_adjacent_edges(&graph, NodeIndex(0), "A",
+                            &[],
+                            &[]);
+        test_adjacent_edges(&graph, NodeIndex(1), "C",
+                            &[("EC", "E")],
+                            &[]);
+        test_adjacent_edges(&graph, NodeIndex(2), "D",
+                            &[],
+                            &[("DE", "E")]);
+        test_adjacent_edges(&graph, NodeIndex(3), "E",
+                            &[("DE", "D")],
+                            &[("EC", "C")]);
+    }
+
+    #[test]
+    fn depth_traverse() {
+        let graph = create_graph();
+        let mut names: Vec<&str> = graph.depth_traverse(NodeIndex(0)).map(|n| *n).collect();
+        names.sort();
+        assert_eq!(names, vec!["A", "B", "C", "D", "E"]);
+    }
+
+    fn traverse(graph: &TestGraph, start: uint, dirs: &[Direction], max_depth: uint) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = graph.depth_traverse_limited(NodeIndex(start), dirs, max_depth)
+                                                .map(|n| *n).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn depth_traverse_limited() {
+        let graph = create_graph();
+        assert_eq!(traverse(&graph, 1, &[Outgoing], 1), vec!["B", "C", "D"]);
+        assert_eq!(traverse(&graph, 2, &[Incoming], 1), vec!["B", "C", "E"]);
+        assert_eq!(traverse(&graph, 2, &[Incoming], 2), vec!["A", "B", "C", "D", "E", "F"]);
+        assert_eq!(traverse(&graph, 3, &[Outgoing, Incoming], 1), vec!["B", "D", "E"]);
+        assert_eq!(traverse(&graph, 0, &[Outgoing], 0), vec!["A"]);
+        assert_eq!(traverse(&graph, 0, &[Outgoing], 2), vec!["A", "B", "C", "D"]);
+    }
+
+    #[test]
+    fn depth_traverse_finds_shortest_paths() {
+        // Going from A, C is first reached the long way round through D and
+        // E, at the depth limit, but it is also right next to A, so B and X
+        // behind it are within reach.
+        let mut graph = Graph::new();
+        let a = graph.add_node("A");
+        let b = graph.add_node("B");
+        let c = graph.add_node("C");
+        let d = graph.add_node("D");
+        let e = graph.add_node("E");
+        let x = graph.add_node("X");
+        graph.add_edge(a, d, "AD");
+        graph.add_edge(a, c, "AC");
+        graph.add_edge(c, b, "CB");
+        graph.add_edge(b, x, "BX");
+        graph.add_edge(d, e, "DE");
+        graph.add_edge(e, c, "EC");
+        assert_eq!(traverse(&graph, 0, &[Outgoing], 3), vec!["A", "B", "C", "D", "E", "X"]);
+    }
+
+    #[test]
+    fn strongly_connected_components() {
+        let graph = create_graph();
+        let components: Vec<Vec<uint>> = graph.strongly_connected_components().iter()
+            .map(|c| c.iter().map(|n| n.get()).collect())
+            .collect();
+        assert_eq!(components, vec![vec![2], vec![4], vec![3], vec![1], vec![0], vec![5]]);
+
+        //    A --> B --> C --> D <-> E     F --+
+        //    ^           |                 ^   |
+        //    +-----------+                 +---+
+        let mut graph = Graph::new();
+        let a = graph.add_node("A");
+        let b = graph.add_node("B");
+        let c = graph.add_node("C");
+        let d = graph.add_node("D");
+        let e = graph.add_node("E");
+        let f = graph.add_node("F");
+        graph.add_edge(a, b, "AB");
+        graph.add_edge(b, c, "BC");
+        graph.add_edge(c, a, "CA");
+        graph.add_edge(c, d, "CD");
+        graph.add_edge(d, e, "DE");
+        graph.add_edge(e, d, "ED");
+        graph.add_edge(f, f, "FF");
+        let components: Vec<Vec<&str>> = graph.strongly_connected_components().iter()
+            .map(|c| c.iter().map(|&n| *graph.node_data(n)).collect())
+            .collect();
+        assert_eq!(components, vec![vec!["D", "E"], vec!["A", "B", "C"], vec!["F"]]);
+    }
+
+    #[test]
+    fn topological_layers() {
+        let graph = create_graph();
+        assert_eq!(graph.topological_layers(), vec![4, 3, 0, 2, 1, 4]);
+
+        //    A --> B <-> C --> D
+        let mut graph = Graph::new();
+        let a = graph.add_node("A");
+        let b = graph.add_node("B");
+        let c = graph.add_node("C");
+        let d = graph.add_node("D");
+        graph.add_edge(a, b, "AB");
+        graph.add_edge(b, c, "BC");
+        graph.add_edge(c, b, "CB");
+        graph.add_edge(c, d, "CD");
+        assert_eq!(graph.topological_layers(), vec![2, 1, 1, 0]);
+    }
+
+    #[test]
+    fn redundant_edges() {
+        let graph = create_graph();
+        let redundant: Vec<&str> = graph.redundant_edges().iter().map(|&e| *graph.edge_data(e)).collect();
+        assert_eq!(redundant, vec!["BC"]);
+
+        // B and C form a cycle, taken as a single node: nothing within it is
+        // redundant, nor is AC alongside AB, but AD is implied by going
+        // through it.
+        let mut graph = Graph::new();
+        let a = graph.add_node("A");
+        let b = graph.add_node("B");
+        let c = graph.add_node("C");
+        let d = graph.add_node("D");
+        graph.add_edge(a, b, "AB");
+        graph.add_edge(b, c, "BC");
+        graph.add_edge(c, b, "CB");
+        graph.add_edge(c, d, "CD");
+        graph.add_edge(a, d, "AD");
+        graph.add_edge(a, c, "AC");
+        let redundant: Vec<&str> = graph.redundant_edges().iter().map(|&e| *graph.edge_data(e)).collect();
+        assert_eq!(redundant, vec!["AD"]);
+    }
+
+    #[test]
+    fn degrees() {
+        let graph = create_graph();
+        let incoming: Vec<uint> = range(0, 6).map(|i| graph.degree(NodeIndex(i), Incoming)).collect();
+        let outgoing: Vec<uint> = range(0, 6).map(|i| graph.degree(NodeIndex(i), Outgoing)).collect();
+        assert_eq!(incoming, vec![0, 2, 2, 1, 1, 0]);
+        assert_eq!(outgoing, vec![1, 2, 0, 1, 1, 1]);
+    }
 }
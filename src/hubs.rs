@@ -0,0 +1,221 @@
+//! Hubs are the few types nearly everything refers to, such as a logger or a
+//! common base class. Their edges crowd out everything else in a drawing, so
+//! they can be left out in favour of a marker on each type referring to them.
+
+use std::collections::HashMap;
+use graph::{Graph, NodeIndex, Incoming};
+use backend::{GraphInfo, TypeNode, EdgeInfo};
+use self::Mode::{Drop, Collapse, Legend};
+
+/// When a type counts as a hub, going by the number of edges into it.
+#[derive(Clone, Copy, Show)]
+pub enum Threshold {
+    /// More edges than this.
+    Degree(usize),
+    /// More edges than this percentage of all types have, from 0 to 100.
+    Percentile(usize),
+}
+
+/// What becomes of the hubs.
+#[derive(Clone, Copy, PartialEq, Show)]
+pub enum Mode {
+    /// Leave the hubs and their edges out.
+    Drop,
+    /// Keep the hubs, but leave out the edges into them.
+    Collapse,
+    /// Leave the hubs and their edges out, and list them in a legend.
+    Legend,
+}
+
+impl Mode {
+    pub fn from_name(name: &str) -> Option<Mode> {
+        match name {
+            "drop" => Some(Drop),
+            "collapse" => Some(Collapse),
+            "legend" => Some(Legend),
+            _ => None,
+        }
+    }
+}
+
+/// The hubs of the graph, in node order.
+pub fn find_hubs(graph: &Graph<TypeNode, EdgeInfo>, threshold: Threshold) -> Vec<NodeIndex> {
+    let n = graph.all_nodes().len();
+    let degrees: Vec<usize> = range(0, n).map(|i| graph.degree(NodeIndex(i), Incoming)).collect();
+
+    let limit = match threshold {
+        Threshold::Degree(limit) => limit,
+        Threshold::Percentile(_) if n == 0 => return vec![],
+        Threshold::Percentile(p) => {
+            // The nearest rank method: the smallest degree at least p percent
+            // of the types have no more than.
+            let mut sorted = degrees.clone();
+            sorted.sort();
+            let rank = (p * n + 99) / 100;
+            sorted[if rank == 0 { 0 } else if rank > n { n - 1 } else { rank - 1 }]
+        },
+    };
+
+    range(0, n).filter(|&i| degrees[i] > limit).map(|i| NodeIndex(i)).collect()
+}
+
+/// Copy of the graph with the hubs suppressed. Each type referring to a hub
+/// is marked with the hub's name instead, or with a key into the legend.
+pub fn suppress(ginfo: &GraphInfo, threshold: Threshold, mode: Mode) -> GraphInfo {
+    let hubs = find_hubs(&ginfo.graph, threshold);
+
+    // Hubs are marked by their short names, unless two of them share one.
+    let mut short_names = HashMap::new();
+    for &hub in hubs.iter() {
+        let short = short_name(&ginfo.graph.node_data(hub).name[]);
+        let count = short_names.get(&short).map(|n| *n).unwrap_or(0);
+        short_names.insert(short, count + 1);
+    }
+
+    let mut markers = HashMap::new();
+    let mut legend = ginfo.legend.clone();
+    for hub in hubs.into_iter() {
+        let name = &ginfo.graph.node_data(hub).name;
+        let marker = if mode == Legend {
+            let key = format!("H{}", legend.len() + 1);
+            legend.push((key.clone(), name.clone()));
+            key
+        } else {
+            let short = short_name(&name[]);
+            if short_names.get(&short) == Some(&1) { short } else { name.clone() }
+        };
+        markers.insert(hub.node_id(), marker);
+    }
+
+    // Where each node that is kept ends up in the new graph.
+    let mut graph = Graph::new();
+    let mut indices = HashMap::new();
+    ginfo.graph.each_node(|i, node| {
+        if mode == Collapse || !markers.contains_key(&i.node_id()) {
+            indices.insert(i.node_id(), graph.add_node(node.data.clone()));
+        }
+        true
+    });
+
+    ginfo.graph.each_edge(|_, edge| {
+        let source = indices.get(&edge.source().node_id()).map(|i| *i);
+        let target = indices.get(&edge.target().node_id()).map(|i| *i);
+        match (source, markers.get(&edge.target().node_id())) {
+            (Some(source), Some(marker)) => {
+                let node_markers = &mut graph.mut_node_data(source).markers;
+                if !node_markers.contains(marker) {
+                    node_markers.push(marker.clone());
+                }
+            },
+            (Some(source), None) => {
+                graph.add_edge(source, target.unwrap(), edge.data.clone());
+            },
+            (None, _) => {},
+        }
+        true
+    });
+
+    GraphInfo { name: ginfo.name.clone(), graph: graph, legend: legend }
+}
+
+/// The last part of a dotted name, ignoring dots within type arguments.
+fn short_name(name: &str) -> String {
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in name.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            '.' if depth == 0 => start = i + 1,
+            _ => {},
+        }
+    }
+    name[start..].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Mode::{Drop, Collapse, Legend};
+    use graph::{Graph, NodeIndex};
+    use backend::GraphInfo;
+    use test_support::test_graph;
+    use backend::EdgeKind;
+    use backend::EdgeKind::Field;
+
+    // Everything refers to N.Log, and A to N.Config<T.U> too.
+    fn create_graph() -> GraphInfo {
+        test_graph(&["A", "B", "C", "N.Log", "N.Config<T.U>"],
+                   &[(0, 1, Field), (0, 3, Field), (1, 3, Field), (2, 3, Field), (4, 3, Field), (0, 4, Field),
+                     (3, 2, Field)])
+    }
+
+    fn describe(ginfo: &GraphInfo) -> (Vec<(String, Vec<String>)>, Vec<(String, String)>) {
+        let g = &ginfo.graph;
+        let nodes = g.all_nodes().iter().map(|node| (node.data.name.clone(), node.data.markers.clone())).collect();
+        let edges = g.all_edges().iter()
+            .map(|edge| (g.node_data(edge.source()).name.clone(), g.node_data(edge.target()).name.clone()))
+            .collect();
+        (nodes, edges)
+    }
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn thresholds() {
+        let ginfo = create_graph();
+        assert_eq!(find_hubs(&ginfo.graph, Threshold::Degree(1)), vec![NodeIndex(3)]);
+        assert_eq!(find_hubs(&ginfo.graph, Threshold::Degree(0)),
+                   vec![NodeIndex(1), NodeIndex(2), NodeIndex(3), NodeIndex(4)]);
+        assert_eq!(find_hubs(&ginfo.graph, Threshold::Percentile(80)), vec![NodeIndex(3)]);
+        assert_eq!(find_hubs(&ginfo.graph, Threshold::Percentile(100)), vec![]);
+        assert_eq!(find_hubs(&Graph::new(), Threshold::Percentile(50)), vec![]);
+    }
+
+    #[test]
+    fn percentile_rank() {
+        // Of ten types, the tenth percentile is the one with the fewest edges
+        // into it: A, with none.
+        let names = ["A", "B", "C", "D", "E", "F", "G", "H", "I", "J"];
+        let mut edges: Vec<(usize, usize, EdgeKind)> = range(1, 10).map(|i| (0, i, Field)).collect();
+        edges.push((1, 2, Field));
+        let ginfo = test_graph(&names, &edges[]);
+        assert_eq!(find_hubs(&ginfo.graph, Threshold::Percentile(10)),
+                   range(1, 10).map(|i| NodeIndex(i)).collect::<Vec<_>>());
+        assert_eq!(find_hubs(&ginfo.graph, Threshold::Percentile(20)), vec![NodeIndex(2)]);
+    }
+
+    #[test]
+    fn shared_short_names() {
+        let ginfo = test_graph(&["X", "A.Log", "B.Log", "C.Config"],
+                               &[(0, 1, Field), (0, 2, Field), (0, 3, Field)]);
+        let (nodes, _) = describe(&suppress(&ginfo, Threshold::Degree(0), Drop));
+        assert_eq!(nodes, vec![("X".to_string(), strings(&["A.Log", "B.Log", "Config"]))]);
+    }
+
+    #[test]
+    fn modes() {
+        let ginfo = create_graph();
+
+        let (nodes, edges) = describe(&suppress(&ginfo, Threshold::Degree(1), Drop));
+        assert_eq!(nodes, vec![("A".to_string(), strings(&["Log"])), ("B".to_string(), strings(&["Log"])),
+                               ("C".to_string(), strings(&["Log"])), ("N.Config<T.U>".to_string(), strings(&["Log"]))]);
+        assert_eq!(edges, vec![("A".to_string(), "B".to_string()), ("A".to_string(), "N.Config<T.U>".to_string())]);
+
+        let (nodes, edges) = describe(&suppress(&ginfo, Threshold::Degree(1), Collapse));
+        assert_eq!(nodes.len(), 5);
+        assert_eq!(nodes[3], ("N.Log".to_string(), vec![]));
+        assert_eq!(edges, vec![("A".to_string(), "B".to_string()), ("A".to_string(), "N.Config<T.U>".to_string()),
+                               ("N.Log".to_string(), "C".to_string())]);
+
+        let ginfo = suppress(&ginfo, Threshold::Degree(0), Legend);
+        let (nodes, edges) = describe(&ginfo);
+        assert_eq!(nodes, vec![("A".to_string(), strings(&["H1", "H3", "H4"]))]);
+        assert_eq!(edges, vec![]);
+        assert_eq!(ginfo.legend, vec![("H1".to_string(), "B".to_string()), ("H2".to_string(), "C".to_string()),
+                                      ("H3".to_string(), "N.Log".to_string()),
+                                      ("H4".to_string(), "N.Config<T.U>".to_string())]);
+    }
+}
@@ -0,0 +1,93 @@
+//! Layering types by what depends on what: the types at the bottom depend on
+//! nothing else, and those at the top orchestrate everything below them.
+
+use std::io::IoResult;
+use graph::{Graph, EdgeIndex};
+use backend::{GraphInfo, TypeNode, EdgeInfo};
+use cycles::write_edge;
+
+/// The edges that don't lead down to a lower layer. With the layers of
+/// `Graph::topological_layers` these are the edges within cycles.
+pub fn upward_edges(graph: &Graph<TypeNode, EdgeInfo>, layers: &[usize]) -> Vec<EdgeIndex> {
+    let mut upward = vec![];
+    graph.each_edge(|i, edge| {
+        if layers[edge.source().node_id()] <= layers[edge.target().node_id()] {
+            upward.push(i);
+        }
+        true
+    });
+    upward
+}
+
+/// Write the layers as text, from the bottom up, then the edges violating
+/// them with where each is found.
+pub fn report<W: Writer>(ginfo: &GraphInfo, w: &mut W) -> IoResult<()> {
+    let g = &ginfo.graph;
+    let layers = g.topological_layers();
+
+    let top = layers.iter().map(|&l| l).max().unwrap_or(0);
+    for layer in range(0, top + 1) {
+        if g.all_nodes().is_empty() {
+            break;
+        }
+        try!(writeln!(w, "Layer {}:", layer));
+        for (i, node) in g.all_nodes().iter().enumerate() {
+            if layers[i] == layer {
+                try!(writeln!(w, "    {}", node.data.name));
+            }
+        }
+    }
+
+    let upward = upward_edges(g, &layers[]);
+    if upward.is_empty() {
+        return writeln!(w, "No upward edges.");
+    }
+    try!(writeln!(w, "Upward edges:"));
+    for &idx in upward.iter() {
+        try!(write_edge(g, idx, w));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::GraphInfo;
+    use test_support::test_graph;
+    use backend::EdgeKind::{Field, Reference};
+
+    //    App --> Service <-> Repo --> Row
+    //     |                            ^
+    //     +----------------------------+
+    fn create_graph() -> GraphInfo {
+        test_graph(&["App", "Service", "Repo", "Row"],
+                   &[(0, 1, Field), (1, 2, Field), (2, 1, Reference), (2, 3, Field), (0, 3, Reference)])
+    }
+
+    #[test]
+    fn upward() {
+        let ginfo = create_graph();
+        let layers = ginfo.graph.topological_layers();
+        assert_eq!(layers, vec![2, 1, 1, 0]);
+        let upward: Vec<usize> = upward_edges(&ginfo.graph, &layers[]).iter().map(|e| e.edge_id()).collect();
+        assert_eq!(upward, vec![1, 2]);
+    }
+
+    #[test]
+    fn reports() {
+        let mut out = vec![];
+        report(&create_graph(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+Layer 0:
+    Row
+Layer 1:
+    Service
+    Repo
+Layer 2:
+    App
+Upward edges:
+    Service -> Repo (field, 0.cs:2:1)
+    Repo -> Service (reference, 0.cs:3:1)
+");
+    }
+}
@@ -1,9 +1,18 @@
 use regex::Regex;
 
 pub use self::Token::{Matched, Unmatched};
+use self::Matcher::{Pattern, Scanner};
 
 pub struct Lexer<T> {
-    tokens: Vec<(T, Regex)>,
+    tokens: Vec<(T, Matcher)>,
+}
+
+/// How a token is recognised at the start of the remaining text: by a regex
+/// anchored with `^`, or by a function returning the length of the token,
+/// for tokens such as nested constructs that a regex can't describe.
+enum Matcher {
+    Pattern(Regex),
+    Scanner(fn(&str) -> Option<usize>),
 }
 
 impl<T> Lexer<T> {
@@ -12,7 +21,14 @@ impl<T> Lexer<T> {
     }
 
     pub fn define_token(&mut self, class: T, regex: Regex) {
-        self.tokens.push((class, regex));
+        self.tokens.push((class, Pattern(regex)));
+    }
+
+    /// Define a token recognised by a function rather than a regex. Given the
+    /// remaining text, it returns the length in bytes of the token starting
+    /// it, or `None` if there is no such token.
+    pub fn define_scanned_token(&mut self, class: T, scanner: fn(&str) -> Option<usize>) {
+        self.tokens.push((class, Scanner(scanner)));
     }
 
     pub fn lex(&self, text: &str) -> TokenIterator<T> {
@@ -26,36 +42,77 @@ pub enum Token<T> {
     Unmatched,
 }
 
+/// Where a token is in the text: its byte offsets, and the line and column
+/// it starts at, both counted from 1. Columns count characters.
+#[derive(PartialEq, Eq, Clone, Copy, Show)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The span from the start of this one to the end of another.
+    pub fn to(&self, other: Span) -> Span {
+        Span { end: other.end, ..*self }
+    }
+}
+
 pub struct TokenIterator<'a, 'b, T: 'a> {
-    tokens: &'a [(T, Regex)],
+    tokens: &'a [(T, Matcher)],
     text: String,
     idx: usize,
+    line: usize,
+    column: usize,
 }
 
 impl<'a, 'b, T> TokenIterator<'a, 'b, T> {
-    fn new(tokens: &'a [(T, Regex)], text: &str) -> TokenIterator<'a, 'b, T> {
-        TokenIterator { tokens: tokens, text: String::from_str(text), idx: 0 }
+    fn new(tokens: &'a [(T, Matcher)], text: &str) -> TokenIterator<'a, 'b, T> {
+        TokenIterator { tokens: tokens, text: String::from_str(text), idx: 0, line: 1, column: 1 }
+    }
+
+    /// Move past `len` bytes of text, returning the span they make up.
+    fn advance(&mut self, len: usize) -> Span {
+        let span = Span { start: self.idx, end: self.idx + len, line: self.line, column: self.column };
+        let mut chars = self.text[self.idx..self.idx + len].chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' if chars.peek() == Some(&'\n') => {},
+                '\r' | '\n' | '\u{2028}' | '\u{2029}' => {
+                    self.line += 1;
+                    self.column = 1;
+                },
+                _ => { self.column += 1; },
+            }
+        }
+        self.idx += len;
+        span
     }
 }
 
 impl<'a, 'b, T: Clone> Iterator for TokenIterator<'a, 'b, T> {
-    type Item = (Token<T>, &'b str);
+    type Item = (Token<T>, &'b str, Span);
 
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
         if self.idx == self.text.len() {
             None
         } else {
             let textleft = &self.text[self.idx..];
-            for &(ref class, ref regex) in self.tokens.iter() {
-                if let Some((begin, end)) = regex.find(textleft) {
-                    self.idx += end;
-                    return Some((Matched(class.clone()), &textleft[begin..end]));
+            for &(ref class, ref matcher) in self.tokens.iter() {
+                let found = match *matcher {
+                    Pattern(ref regex) => regex.find(textleft),
+                    Scanner(scanner) => scanner(textleft).and_then(|n| if n > 0 { Some((0, n)) } else { None }),
+                };
+                if let Some((begin, end)) = found {
+                    let span = self.advance(end);
+                    return Some((Matched(class.clone()), &textleft[begin..end], span));
                 }
             }
 
             let ch = textleft.slice_chars(0, 1);
-            self.idx += ch.len();
-            Some((Unmatched, ch))
+            let span = self.advance(ch.len());
+            Some((Unmatched, ch, span))
         }
     }
 
@@ -76,12 +133,65 @@ mod tests {
         lexer.define_token("letters", regex!(r"^([a-zA-Z])+"));
         lexer.define_token("numbers", regex!(r"^[0-9]+"));
 
-        let vec: Vec<(Token<&str>, &str)> = lexer.lex("apple bat 42  cat").collect();
+        let vec: Vec<(Token<&str>, &str)> = lexer.lex("apple bat 42  cat").map(|(tok, text, _)| (tok, text)).collect();
         let tokentypes: Vec<&str> = vec.iter().map(|v| match v.0 { Matched(x) => x, _ => panic!("nope") } ).collect();
         let texts: Vec<&str> = vec.iter().map(|v| v.1.clone()).collect();
 
         assert_eq!(&tokentypes[], ["letters", "space", "letters", "space", "numbers", "space", "letters"]);
         assert_eq!(&texts[], ["apple", " ", "bat", " ", "42", "  ", "cat"]);
     }
+
+    fn parenthesized(text: &str) -> Option<usize> {
+        let mut depth = 0;
+        for (i, c) in text.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i + 1);
+                    }
+                },
+                _ if depth == 0 => return None,
+                _ => {},
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn scanned_tokens() {
+        let mut lexer = Lexer::new();
+        lexer.define_token("space", regex!(r"^ +"));
+        lexer.define_scanned_token("parens", parenthesized);
+        lexer.define_token("letters", regex!(r"^([a-zA-Z])+"));
+
+        let vec: Vec<(Token<&str>, &str)> = lexer.lex("(a (b)) c (").map(|(tok, text, _)| (tok, text)).collect();
+        let texts: Vec<&str> = vec.iter().map(|v| v.1.clone()).collect();
+
+        assert_eq!(&texts[], ["(a (b))", " ", "c", " ", "("]);
+        assert_eq!(vec[0].0, Matched("parens"));
+        assert_eq!(vec[4].0, Unmatched);
+    }
+
+    #[test]
+    fn token_spans() {
+        let mut lexer = Lexer::new();
+        lexer.define_token("space", regex!(r"^[ \r\n]+"));
+        lexer.define_token("letters", regex!(r"^([a-zA-Zé])+"));
+
+        let spans: Vec<Span> = lexer.lex("ab \n  é c\r\nd").map(|v| v.2).collect();
+        let expected = [
+            Span { start: 0, end: 2, line: 1, column: 1 },
+            Span { start: 2, end: 6, line: 1, column: 3 },
+            Span { start: 6, end: 8, line: 2, column: 3 },
+            Span { start: 8, end: 9, line: 2, column: 4 },
+            Span { start: 9, end: 10, line: 2, column: 5 },
+            Span { start: 10, end: 12, line: 2, column: 6 },
+            Span { start: 12, end: 13, line: 3, column: 1 },
+        ];
+        assert_eq!(&spans[], &expected[]);
+        assert_eq!(expected[0].to(expected[2]), Span { start: 0, end: 8, line: 1, column: 1 });
+    }
 }
 
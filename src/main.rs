@@ -11,11 +11,11 @@ extern crate docopt;
 extern crate regex;
 #[no_link] #[plugin] extern crate regex_macros;
 
-extern crate graphviz;
 extern crate test;
 
 use docopt::Docopt;
 use backend::LanguageBackend;
+use backend::{TypeNode, EdgeKind, EdgeInfo};
 use graph::Graph;
 
 mod graph;
@@ -24,17 +24,202 @@ mod files;
 mod lexer;
 mod csharp;
 mod todot;
+mod tojson;
+mod tographml;
+mod togexf;
+mod tomermaid;
+mod toplantuml;
+mod tohtml;
+mod todsm;
+mod xml;
+mod uml;
+mod hubs;
+mod collapse;
+mod focus;
+mod cycles;
+mod reduce;
+mod layers;
 mod bench;
+#[cfg(test)]
+mod test_support;
 
-docopt!(Args derive Show, "Usage: machete <path>");
+docopt!(Args derive Show, "
+Usage: machete [options] <path>
+       machete cycles [options] <path>
+       machete layers [options] <path>
+
+Commands:
+    cycles             List the dependency cycles, each with its types and
+                       the edges between them, instead of drawing the graph.
+    layers             List the types in layers, from those depending on no
+                       others up, and the edges going against the layers.
+
+Options:
+    --format <format>  Write the graph in this format: dot, json for one
+                       document per graph on a line of its own, graphml,
+                       gexf, a mermaid or plantuml class diagram, or html
+                       for a page to explore the graph in a browser, or
+                       dsm for a dependency structure matrix.
+                       [default: dot]
+    --edges <kinds>    Only draw edges of these comma separated kinds:
+                       inheritance, implementation, contains, constraint,
+                       field, parameter, instantiation, static and reference.
+    --nested-clusters  Draw nested types inside their containing type.
+    --namespace-clusters
+                       Draw types inside a cluster for their namespace.
+    --project-clusters
+                       Draw types inside a cluster for the project they
+                       belong to.
+    --collapse <namespaces>
+                       Draw each of these comma separated namespaces, along
+                       with the namespaces inside it, as a single node.
+    --condense         Draw the types of each dependency cycle as a single
+                       node, leaving layers of types without cycles.
+    --reduce           Leave out edges implied by longer paths.
+    --keep-implied     With --reduce, draw the edges it leaves out as faint
+                       dashed lines instead.
+    --dsm-order <order>
+                       Order the rows and columns of the matrix by
+                       namespace, or topological for the types used before
+                       the types using them. [default: topological]
+    --dsm-style <style>
+                       Write the matrix as csv, text or html.
+                       [default: text]
+    --rankdir <dir>    Lay the graph out in this direction: TB, BT, LR or RL.
+    --rank-layers      Draw the types of each layer at the same rank.
+    --color-cycles     Fill the types of each dependency cycle with a colour
+                       of their own.
+    --ignore-bcl       Leave out references to well known .NET types such
+                       as String, List and Task.
+    --define <symbols>
+                       Comma separated preprocessor symbols to consider
+                       defined, besides those of the project files.
+    --configuration <name>
+                       Take the symbols of the project files from this
+                       configuration. [default: Debug]
+    --hub-degree <n>   Suppress hubs, the types with more than n edges
+                       into them.
+    --hub-percentile <p>
+                       Suppress hubs, the types with more edges into them
+                       than p percent of all types have.
+    --hub-mode <mode>  What to do with hubs: drop them, collapse the edges
+                       into them, or list them in a legend. Types referring
+                       to a hub are marked with it instead.
+                       [default: collapse]
+    --focus <type>     Only draw the types around this one, named with or
+                       without its namespace and type arguments.
+    --depth <n>        How many edges away from the focused type to go.
+                       [default: 1]
+    --direction <dir>  Which edges to follow from the focused type: out to
+                       the types it uses, in to the types using it, or both.
+                       [default: both]
+", flag_edges: Option<String>, flag_define: Option<String>, flag_hub_degree: Option<usize>,
+   flag_hub_percentile: Option<usize>, flag_collapse: Option<String>,
+   flag_focus: Option<String>, flag_depth: usize, flag_rankdir: Option<String>);
 
 fn main() {
     let args: Args = Args::docopt().decode().unwrap_or_else(|e| e.exit());
-    run(args.arg_path);
+    run(args);
 }
 
-fn run(path: String) {
-    let backends = [csharp::Csharp];
+fn run(args: Args) {
+    let path = args.arg_path;
+
+    let kinds = match args.flag_edges {
+        Some(ref names) => {
+            let mut kinds = vec![];
+            for name in names.split(',') {
+                match EdgeKind::from_name(name.trim()) {
+                    Some(kind) => kinds.push(kind),
+                    None => {
+                        println!("machete: {}: No such edge kind.", name);
+                        return;
+                    },
+                }
+            }
+            kinds
+        },
+        None => EdgeKind::all(),
+    };
+
+    let threshold = match (args.flag_hub_degree, args.flag_hub_percentile) {
+        (Some(_), Some(_)) => {
+            println!("machete: Only one of --hub-degree and --hub-percentile can be given.");
+            return;
+        },
+        (Some(n), None) => Some(hubs::Threshold::Degree(n)),
+        (None, Some(p)) if p > 100 => {
+            println!("machete: {}: Percentile must be between 0 and 100.", p);
+            return;
+        },
+        (None, Some(p)) => Some(hubs::Threshold::Percentile(p)),
+        (None, None) => None,
+    };
+    let hub_mode = match hubs::Mode::from_name(&args.flag_hub_mode[]) {
+        Some(mode) => mode,
+        None => {
+            println!("machete: {}: No such hub mode.", args.flag_hub_mode);
+            return;
+        },
+    };
+
+    let directions = match focus::directions_from_name(&args.flag_direction[]) {
+        Some(directions) => directions,
+        None => {
+            println!("machete: {}: No such direction.", args.flag_direction);
+            return;
+        },
+    };
+
+    if !["dot", "json", "graphml", "gexf", "mermaid", "plantuml", "html", "dsm"].contains(&&args.flag_format[]) {
+        println!("machete: {}: No such format.", args.flag_format);
+        return;
+    }
+
+    let dsm_order = match todsm::Order::from_name(&args.flag_dsm_order[]) {
+        Some(order) => order,
+        None => {
+            println!("machete: {}: No such order.", args.flag_dsm_order);
+            return;
+        },
+    };
+    let dsm_style = match todsm::Style::from_name(&args.flag_dsm_style[]) {
+        Some(style) => style,
+        None => {
+            println!("machete: {}: No such matrix style.", args.flag_dsm_style);
+            return;
+        },
+    };
+
+    let rank_dir = match args.flag_rankdir {
+        Some(ref dir) if ["TB", "BT", "LR", "RL"].contains(&&dir[]) => Some(dir.clone()),
+        Some(ref dir) => {
+            println!("machete: {}: No such direction.", dir);
+            return;
+        },
+        None => None,
+    };
+
+    let mut dot_options = todot::Options::new();
+    dot_options.nested_clusters = args.flag_nested_clusters;
+    dot_options.namespace_clusters = args.flag_namespace_clusters;
+    dot_options.project_clusters = args.flag_project_clusters;
+    dot_options.cycle_colors = args.flag_color_cycles;
+    dot_options.rank_dir = rank_dir;
+    dot_options.rank_layers = args.flag_rank_layers;
+
+    let collapsed: Vec<String> = match args.flag_collapse {
+        Some(ref names) => names.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
+        None => vec![],
+    };
+
+    let defines = match args.flag_define {
+        Some(ref symbols) => symbols.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
+        None => vec![],
+    };
+
+    let backends = [csharp::Csharp { ignore_bcl: args.flag_ignore_bcl, defines: defines,
+                                     configuration: args.flag_configuration.clone() }];
 
     let mut exts = vec![];
     for backend in backends.iter() {
@@ -65,22 +250,65 @@ fn run(path: String) {
 
         let mut out = std::io::stdio::stdout();
         for g in gs.iter() {
-            todot::render(g, &mut out);
+            let mut g = g.with_edge_kinds(&kinds[]);
+            if let Some(ref name) = args.flag_focus {
+                let found = focus::find_types(&g.graph, &name[]);
+                match found.len() {
+                    0 => {
+                        println!("machete: {}: No such type.", name);
+                        return;
+                    },
+                    1 => g = focus::neighbourhood(&g, found[0], args.flag_depth, &directions[]),
+                    _ => {
+                        let names: Vec<String> = found.iter().map(|&i| g.graph.node_data(i).name.clone()).collect();
+                        println!("machete: {}: Ambiguous type, could be {}.", name, names.connect(", "));
+                        return;
+                    },
+                }
+            }
+            if !collapsed.is_empty() {
+                g = collapse::collapse_namespaces(&g, &collapsed[]);
+            }
+            if args.flag_condense {
+                g = collapse::collapse_cycles(&g);
+            }
+            let g = match threshold {
+                Some(threshold) => hubs::suppress(&g, threshold, hub_mode),
+                None => g,
+            };
+            let g = if args.flag_reduce { reduce::reduce(&g, args.flag_keep_implied) } else { g };
+            if args.cmd_cycles {
+                cycles::report(&g, &mut out).unwrap();
+            } else if args.cmd_layers {
+                layers::report(&g, &mut out).unwrap();
+            } else {
+                match &args.flag_format[] {
+                    "json" => tojson::render(&g, &mut out),
+                    "graphml" => tographml::render(&g, &mut out),
+                    "gexf" => togexf::render(&g, &mut out),
+                    "mermaid" => tomermaid::render(&g, &mut out),
+                    "plantuml" => toplantuml::render(&g, &mut out),
+                    "html" => tohtml::render(&g, &mut out),
+                    "dsm" => todsm::render(&g, dsm_order, dsm_style, &mut out),
+                    _ => todot::render(&g, &dot_options, &mut out),
+                }
+            }
         }
     }
 }
 
-fn print_ascii_graph(g: &Graph<String, ()>) {
+fn print_ascii_graph(g: &Graph<TypeNode, EdgeInfo>) {
     println!("Nodes:");
     g.each_node(|_, node| {
-        println!("\t{}", node.data);
+        println!("\t{} ({})", node.data.name, node.data.kind.name());
         true
     });
     println!("Edges:");
     g.each_edge(|_, edge| {
-        println!("\t({}, {})",
-                 g.node_data(edge.source()),
-                 g.node_data(edge.target()));
+        println!("\t({}, {}, {})",
+                 g.node_data(edge.source()).name,
+                 g.node_data(edge.target()).name,
+                 edge.data.kind.name());
         true
     });
 }
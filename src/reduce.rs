@@ -0,0 +1,57 @@
+//! Transitive reduction: an edge from A to C says nothing new when there is
+//! already a path from A to C through B, so it can go without losing any
+//! information about what depends on what.
+
+use std::collections::HashSet;
+use graph::Graph;
+use backend::GraphInfo;
+
+/// Copy of the graph without the edges implied by longer paths, or with them
+/// marked as implied if they're to be kept.
+pub fn reduce(ginfo: &GraphInfo, keep_implied: bool) -> GraphInfo {
+    let redundant: HashSet<usize> = ginfo.graph.redundant_edges().iter().map(|e| e.edge_id()).collect();
+
+    let mut graph = Graph::new();
+    ginfo.graph.each_node(|_, node| {
+        graph.add_node(node.data.clone());
+        true
+    });
+    ginfo.graph.each_edge(|i, edge| {
+        if !redundant.contains(&i.edge_id()) {
+            graph.add_edge(edge.source(), edge.target(), edge.data.clone());
+        } else if keep_implied {
+            let mut info = edge.data.clone();
+            info.implied = true;
+            graph.add_edge(edge.source(), edge.target(), info);
+        }
+        true
+    });
+
+    GraphInfo { name: ginfo.name.clone(), graph: graph, legend: ginfo.legend.clone() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::GraphInfo;
+    use test_support::test_graph;
+    use backend::EdgeKind::Field;
+
+    // A uses B and C, and B uses C, so A doesn't need to use C directly.
+    fn create_graph() -> GraphInfo {
+        test_graph(&["A", "B", "C"], &[(0, 1, Field), (0, 2, Field), (1, 2, Field)])
+    }
+
+    fn edges(ginfo: &GraphInfo) -> Vec<(usize, usize, bool)> {
+        ginfo.graph.all_edges().iter()
+            .map(|edge| (edge.source().node_id(), edge.target().node_id(), edge.data.implied))
+            .collect()
+    }
+
+    #[test]
+    fn reduction() {
+        let ginfo = create_graph();
+        assert_eq!(edges(&reduce(&ginfo, false)), vec![(0, 1, false), (1, 2, false)]);
+        assert_eq!(edges(&reduce(&ginfo, true)), vec![(0, 1, false), (0, 2, true), (1, 2, false)]);
+    }
+}
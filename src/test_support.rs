@@ -0,0 +1,35 @@
+//! Helpers shared by the tests of the modules working on graphs.
+
+use graph::{Graph, NodeIndex};
+use backend::{GraphInfo, TypeNode, EdgeInfo, EdgeKind, Location};
+use backend::TypeKind::Class;
+use lexer::Span;
+use std::path::posix::Path;
+
+/// A graph of a class for each of the names, in the namespace before the last
+/// dot outside of type arguments, and an edge of the given kind for each pair
+/// of indices, the ith of them found on line i + 1 of `0.cs`.
+pub fn test_graph(names: &[&str], edges: &[(usize, usize, EdgeKind)]) -> GraphInfo {
+    let mut graph = Graph::new();
+    for name in names.iter() {
+        let mut depth = 0;
+        let mut dot = None;
+        for (i, c) in name.char_indices() {
+            match c {
+                '<' => depth += 1,
+                '>' => depth -= 1,
+                '.' if depth == 0 => dot = Some(i),
+                _ => {},
+            }
+        }
+        let namespace = dot.map_or(String::new(), |i| name[..i].to_string());
+        graph.add_node(TypeNode { name: name.to_string(), kind: Class, namespace: namespace, project: None,
+                                  locations: vec![], markers: vec![] });
+    }
+    for (i, &(source, target, kind)) in edges.iter().enumerate() {
+        let location = Location { file: Path::new("0.cs"), span: Span { start: 0, end: 0, line: i + 1, column: 1 } };
+        let info = EdgeInfo { kind: kind, location: location, weight: 1, implied: false };
+        graph.add_edge(NodeIndex(source), NodeIndex(target), info);
+    }
+    GraphInfo { name: "test".to_string(), graph: graph, legend: vec![] }
+}
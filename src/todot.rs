@@ -1,45 +1,299 @@
-use std::borrow::IntoCow;
-use graphviz as dot;
-use graph::{Graph, NodeIndex, EdgeIndex};
-use backend::GraphInfo;
+use std::io::IoResult;
+use std::collections::{HashSet, HashMap};
+use regex::Regex;
+use graph::NodeIndex;
+use backend::{GraphInfo, TypeNode};
+use cycles::find_cycles;
+use backend::{EdgeKind, TypeKind};
+use backend::TypeKind::{Class, Struct, Interface, Enum, Record, RecordStruct, Delegate, Namespace, Cycle};
+use backend::EdgeKind::{Inheritance, Implementation, Contains, Constraint, Field, Parameter, Instantiation, StaticAccess,
+                        Reference};
 
-pub type Nd = NodeIndex;
-pub type Ed = EdgeIndex;
+/// Choices about how the graph is drawn.
+pub struct Options {
+    /// Draw nested types inside a cluster for the type containing them,
+    /// instead of with `contains` edges.
+    pub nested_clusters: bool,
+    /// Draw the types of each namespace inside a cluster, nested like the
+    /// namespaces are.
+    pub namespace_clusters: bool,
+    /// Draw the types of each project inside a cluster.
+    pub project_clusters: bool,
+    /// Fill the types of each dependency cycle with a colour of its own.
+    pub cycle_colors: bool,
+    /// The direction the graph is laid out in, such as `BT` for bottom to
+    /// top, if not the default.
+    pub rank_dir: Option<String>,
+    /// Draw the types of each topological layer at the same rank.
+    pub rank_layers: bool,
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Options { nested_clusters: false, namespace_clusters: false, project_clusters: false, cycle_colors: false,
+                  rank_dir: None, rank_layers: false }
+    }
+}
+
+/// The colours cycles are filled with, reused when there are more cycles.
+static CYCLE_COLORS: &'static [&'static str] = &["lightpink", "lightblue", "palegreen", "lightsalmon", "plum", "khaki",
+                                                 "lightcyan", "wheat"];
+
+/// A group of nodes drawn together, such as the types of a namespace, and the
+/// groups inside it. Each is told apart from its siblings by its key.
+struct Cluster {
+    key: String,
+    label: String,
+    nodes: Vec<NodeIndex>,
+    inner: Vec<Cluster>,
+}
+
+impl Cluster {
+    fn new(key: String, label: String) -> Cluster {
+        Cluster { key: key, label: label, nodes: vec![], inner: vec![] }
+    }
+
+    /// The cluster found by following the path of keys and labels down from
+    /// this one, adding clusters as needed.
+    fn find(&mut self, path: &[(String, String)]) -> &mut Cluster {
+        if path.is_empty() {
+            return self;
+        }
+        let (ref key, ref label) = path[0];
+        let pos = match self.inner.iter().position(|c| &c.key == key) {
+            Some(pos) => pos,
+            None => {
+                self.inner.push(Cluster::new(key.clone(), label.clone()));
+                self.inner.len() - 1
+            },
+        };
+        self.inner[pos].find(&path[1..])
+    }
+}
 
 /// Render the graph to the file. Note the graph name must match the regex
 /// [a-zA-Z_][a-zA-Z_0-9]* or this will panic.
-pub fn render<W: Writer>(ginfo: &GraphInfo, output: &mut W) {
-    dot::render(ginfo, output).unwrap()
+pub fn render<W: Writer>(ginfo: &GraphInfo, options: &Options, output: &mut W) {
+    write_graph(ginfo, options, output).unwrap()
+}
+
+fn write_graph<W: Writer>(ginfo: &GraphInfo, options: &Options, w: &mut W) -> IoResult<()> {
+    let id = regex!(r"^[a-zA-Z_][a-zA-Z_0-9]*$");
+    if !id.is_match(&ginfo.name[]) {
+        panic!("Graph name does not conform to required format");
+    }
+
+    try!(writeln!(w, "digraph {} {{", ginfo.name));
+    if let Some(ref dir) = options.rank_dir {
+        try!(writeln!(w, "    rankdir={};", dir));
+    }
+
+    // The nested types directly inside each type, if they're to be drawn
+    // inside it.
+    let mut children: HashMap<usize, Vec<NodeIndex>> = HashMap::new();
+    let mut nested = HashSet::new();
+    if options.nested_clusters {
+        for edge in ginfo.graph.all_edges().iter() {
+            if edge.data.kind == Contains {
+                let outer = edge.source().node_id();
+                if !children.contains_key(&outer) {
+                    children.insert(outer, vec![]);
+                }
+                children.get_mut(&outer).unwrap().push(edge.target());
+                nested.insert(edge.target().node_id());
+            }
+        }
+    }
+
+    // The colour of each type in a cycle, if they're to be coloured.
+    let mut colors = HashMap::new();
+    if options.cycle_colors {
+        for (i, cycle) in find_cycles(&ginfo.graph).iter().enumerate() {
+            for node in cycle.types.iter() {
+                colors.insert(node.node_id(), CYCLE_COLORS[i % CYCLE_COLORS.len()]);
+            }
+        }
+    }
+
+    // The topological layer of each type, if each layer is to be drawn at
+    // the same rank.
+    let layers = if options.rank_layers { ginfo.graph.topological_layers() } else { vec![] };
+
+    let mut root = Cluster::new(String::new(), String::new());
+    for i in range(0, ginfo.graph.all_nodes().len()) {
+        if !nested.contains(&i) {
+            let path = cluster_path(ginfo.graph.node_data(NodeIndex(i)), options);
+            root.find(&path[]).nodes.push(NodeIndex(i));
+        }
+    }
+    try!(write_cluster(ginfo, &root, &children, &colors, &layers[], 1, &mut 0, w));
+
+    for edge in ginfo.graph.all_edges().iter() {
+        if options.nested_clusters && edge.data.kind == Contains {
+            continue;
+        }
+        let weight = if edge.data.weight > 1 { format!(", label=\"{}\"", edge.data.weight) } else { String::new() };
+        let attributes = if edge.data.implied { "style=dashed, color=gray80, constraint=false" }
+                         else { edge_attributes(edge.data.kind) };
+        try!(writeln!(w, "    N{} -> N{}[{}{}];",
+                      edge.source().node_id(), edge.target().node_id(), attributes, weight));
+    }
+
+    if !ginfo.legend.is_empty() {
+        let lines: Vec<String> = ginfo.legend.iter().map(|&(ref key, ref name)| format!("{}: {}", key, name)).collect();
+        try!(writeln!(w, "    subgraph cluster_legend {{"));
+        try!(writeln!(w, "        label=\"Hubs\";"));
+        try!(writeln!(w, "        legend[label={}, shape=plaintext];", quote(&lines.connect("\n")[])));
+        try!(writeln!(w, "    }}"));
+    }
+
+    writeln!(w, "}}")
 }
 
-impl<'a> dot::Labeller<'a, Nd, Ed> for GraphInfo {
-    fn graph_id(&'a self) -> dot::Id<'a> {
-        dot::Id::new(&self.name[]).ok().expect("Graph name does not conform to required format")
+/// The keys and labels of the clusters a type is drawn inside: its project,
+/// then each namespace from the outermost one in.
+fn cluster_path(node: &TypeNode, options: &Options) -> Vec<(String, String)> {
+    let mut path = vec![];
+    if options.project_clusters {
+        if let Some(ref project) = node.project {
+            path.push((format!("project {}", project), project.clone()));
+        }
+    }
+    if options.namespace_clusters && !node.namespace.is_empty() {
+        let mut namespace = String::new();
+        for part in node.namespace.split('.') {
+            if !namespace.is_empty() {
+                namespace.push('.');
+            }
+            namespace.push_str(part);
+            path.push((format!("namespace {}", namespace), namespace.clone()));
+        }
+    }
+    path
+}
+
+/// Write the nodes in a cluster, followed by the clusters inside it, each
+/// numbered from the counter.
+fn write_cluster<W: Writer>(ginfo: &GraphInfo, cluster: &Cluster, children: &HashMap<usize, Vec<NodeIndex>>,
+                            colors: &HashMap<usize, &str>, layers: &[usize], depth: usize, counter: &mut usize,
+                            w: &mut W) -> IoResult<()> {
+    let mut indent = String::new();
+    for _ in range(0, depth) {
+        indent.push_str("    ");
     }
 
-    fn node_id(&'a self, n: &Nd) -> dot::Id<'a> {
-        dot::Id::new(&self.graph.node_data(*n)[]).unwrap()
+    for &n in cluster.nodes.iter() {
+        try!(write_node(ginfo, n, children, colors, layers, depth, w));
+    }
+    try!(write_ranks(&cluster.nodes[], layers, &indent[], w));
+    for inner in cluster.inner.iter() {
+        *counter += 1;
+        try!(writeln!(w, "{}subgraph cluster_{} {{", indent, counter));
+        try!(writeln!(w, "{}    label={};", indent, quote(&inner.label[])));
+        try!(write_cluster(ginfo, inner, children, colors, layers, depth + 1, counter, w));
+        try!(writeln!(w, "{}}}", indent));
     }
+    Ok(())
 }
 
-impl<'a> dot::GraphWalk<'a, Nd, Ed> for GraphInfo {
-    fn nodes(&self) -> dot::Nodes<'a, Nd> {
-        let mut result = vec![];
-        self.graph.each_node(|i, _| {result.push(i); true});
-        result.into_cow()
+/// Write the statement for a node, or a cluster holding it and the types
+/// nested inside it.
+fn write_node<W: Writer>(ginfo: &GraphInfo, n: NodeIndex, children: &HashMap<usize, Vec<NodeIndex>>,
+                         colors: &HashMap<usize, &str>, layers: &[usize], depth: usize, w: &mut W) -> IoResult<()> {
+    let mut indent = String::new();
+    for _ in range(0, depth) {
+        indent.push_str("    ");
     }
+    let node = ginfo.graph.node_data(n);
+
+    // Names may be qualified with dots, which are not allowed in ids, so
+    // nodes are identified by index and the name goes in the label instead.
+    let fill = match colors.get(&n.node_id()) {
+        Some(color) => format!(", style=filled, fillcolor={}", color),
+        None => String::new(),
+    };
+    let statement = format!("N{}[label={}, shape={}{}];",
+                            n.node_id(), quote(&node_label(node)[]), node_shape(node.kind), fill);
 
-    fn edges(&self) -> dot::Edges<'a, Ed> {
-        let mut result = vec![];
-        self.graph.each_edge(|i, _| {result.push(i); true});
-        result.into_cow()
+    match children.get(&n.node_id()) {
+        None => writeln!(w, "{}{}", indent, statement),
+        Some(inner) => {
+            try!(writeln!(w, "{}subgraph cluster_N{} {{", indent, n.node_id()));
+            try!(writeln!(w, "{}    label={};", indent, quote(&node.name[])));
+            try!(writeln!(w, "{}    {}", indent, statement));
+            for &child in inner.iter() {
+                try!(write_node(ginfo, child, children, colors, layers, depth + 1, w));
+            }
+            let mut group = vec![n];
+            group.push_all(&inner[]);
+            try!(write_ranks(&group[], layers, &format!("{}    ", indent)[], w));
+            writeln!(w, "{}}}", indent)
+        },
     }
+}
+
+/// Write a group for each layer holding more than one of the nodes, drawing
+/// them at the same rank, unless there are no layers to draw. Graphviz can't
+/// rank nodes of different clusters together, so the nodes are those drawn
+/// directly inside one cluster.
+fn write_ranks<W: Writer>(nodes: &[NodeIndex], layers: &[usize], indent: &str, w: &mut W) -> IoResult<()> {
+    if layers.is_empty() {
+        return Ok(());
+    }
+    let top = nodes.iter().map(|n| layers[n.node_id()]).max().unwrap_or(0);
+    for layer in range(0, top + 1) {
+        let ids: Vec<String> = nodes.iter()
+            .filter(|n| layers[n.node_id()] == layer)
+            .map(|n| format!("N{};", n.node_id()))
+            .collect();
+        if ids.len() > 1 {
+            try!(writeln!(w, "{}{{ rank=same; {} }}", indent, ids.connect(" ")));
+        }
+    }
+    Ok(())
+}
 
-    fn source(&self, e: &Ed) -> Nd {
-        self.graph.edge(*e).source()
+/// The name of the type, followed by the markers of the hubs it refers to.
+fn node_label(node: &TypeNode) -> String {
+    if node.markers.is_empty() {
+        node.name.clone()
+    } else {
+        format!("{}\nuses {}", node.name, node.markers.connect(", "))
     }
+}
 
-    fn target(&self, e: &Ed) -> Nd {
-        self.graph.edge(*e).target()
+/// Each kind of type gets its own shape.
+fn node_shape(kind: TypeKind) -> &'static str {
+    match kind {
+        Class => "box",
+        Struct => "box3d",
+        Interface => "ellipse",
+        Enum => "hexagon",
+        Record => "note",
+        RecordStruct => "folder",
+        Delegate => "cds",
+        Namespace => "tab",
+        Cycle => "doubleoctagon",
     }
 }
+
+/// Each kind of edge gets its own look, loosely following UML: hollow
+/// arrowheads for inheritance, dashes for weaker dependencies.
+fn edge_attributes(kind: EdgeKind) -> &'static str {
+    match kind {
+        Inheritance => "arrowhead=empty, penwidth=2",
+        Implementation => "arrowhead=empty, style=dashed",
+        Contains => "arrowhead=none, arrowtail=odot, dir=both",
+        Constraint => "arrowhead=empty, style=dotted",
+        Field => "arrowhead=open",
+        Parameter => "arrowhead=open, style=dashed",
+        Instantiation => "arrowhead=vee, style=dashed, color=darkgreen",
+        StaticAccess => "arrowhead=open, style=dotted, color=blue",
+        Reference => "arrowhead=open, style=dotted, color=gray",
+    }
+}
+
+/// Quote a string for use as a DOT id. Newlines become line breaks in labels.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n"))
+}
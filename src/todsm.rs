@@ -0,0 +1,292 @@
+//! Writing the graph as a dependency structure matrix: a row and a column for
+//! each type, with a mark where the type of the row uses the type of the
+//! column. In topological order the types used come first, so marks above
+//! the diagonal stand out as cycles, which are highlighted as blocks.
+
+use std::io::IoResult;
+use graph::{Graph, NodeIndex, Outgoing};
+use backend::{GraphInfo, TypeNode, EdgeInfo};
+use xml::escape;
+use self::Order::{Namespace, Topological};
+use self::Style::{Csv, Text, Html};
+
+/// The order of the rows and columns.
+#[derive(Clone, Copy, PartialEq, Show)]
+pub enum Order {
+    /// By namespace, then by name.
+    Namespace,
+    /// The types used before the types using them, with the types of each
+    /// cycle together.
+    Topological,
+}
+
+impl Order {
+    pub fn from_name(name: &str) -> Option<Order> {
+        match name {
+            "namespace" => Some(Namespace),
+            "topological" => Some(Topological),
+            _ => None,
+        }
+    }
+}
+
+/// How the matrix is written.
+#[derive(Clone, Copy, PartialEq, Show)]
+pub enum Style {
+    Csv,
+    /// A grid of plain text, with dots for the empty cells of cycle blocks.
+    Text,
+    /// A page holding a table, with cycle blocks shaded.
+    Html,
+}
+
+impl Style {
+    pub fn from_name(name: &str) -> Option<Style> {
+        match name {
+            "csv" => Some(Csv),
+            "text" => Some(Text),
+            "html" => Some(Html),
+            _ => None,
+        }
+    }
+}
+
+struct Matrix {
+    /// The type of each row and column.
+    types: Vec<NodeIndex>,
+    /// The number of edges from the type of each row to the type of each
+    /// column.
+    cells: Vec<Vec<usize>>,
+    /// The cycle each type belongs to, if any.
+    cycles: Vec<Option<usize>>,
+}
+
+impl Matrix {
+    fn in_cycle(&self, row: usize, column: usize) -> bool {
+        self.cycles[row].is_some() && self.cycles[row] == self.cycles[column]
+    }
+}
+
+fn build(graph: &Graph<TypeNode, EdgeInfo>, order: Order) -> Matrix {
+    let components = graph.strongly_connected_components();
+    let mut cycle_of: Vec<Option<usize>> = graph.all_nodes().iter().map(|_| None).collect();
+    for (c, component) in components.iter().enumerate() {
+        if component.len() > 1 {
+            for node in component.iter() {
+                cycle_of[node.node_id()] = Some(c);
+            }
+        }
+    }
+
+    let types: Vec<NodeIndex> = match order {
+        Topological => components.iter().flat_map(|component| component.iter().map(|&n| n)).collect(),
+        Namespace => {
+            let mut types: Vec<NodeIndex> = range(0, graph.all_nodes().len()).map(|i| NodeIndex(i)).collect();
+            types.sort_by(|&a, &b| {
+                let (a, b) = (graph.node_data(a), graph.node_data(b));
+                (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name))
+            });
+            types
+        },
+    };
+
+    let mut position: Vec<usize> = types.iter().map(|_| 0).collect();
+    for (i, node) in types.iter().enumerate() {
+        position[node.node_id()] = i;
+    }
+    let cells = types.iter()
+        .map(|&node| {
+            let mut row: Vec<usize> = types.iter().map(|_| 0).collect();
+            for (_, edge) in graph.adjacent_edges(node, Outgoing) {
+                row[position[edge.target().node_id()]] += edge.data.weight;
+            }
+            row
+        })
+        .collect();
+    let cycles = types.iter().map(|node| cycle_of[node.node_id()]).collect();
+
+    Matrix { types: types, cells: cells, cycles: cycles }
+}
+
+/// Render the graph to the file as a matrix.
+pub fn render<W: Writer>(ginfo: &GraphInfo, order: Order, style: Style, output: &mut W) {
+    let matrix = build(&ginfo.graph, order);
+    let result = match style {
+        Csv => write_csv(ginfo, &matrix, output),
+        Text => write_text(ginfo, &matrix, output),
+        Html => write_html(ginfo, &matrix, output),
+    };
+    result.unwrap()
+}
+
+fn write_csv<W: Writer>(ginfo: &GraphInfo, matrix: &Matrix, w: &mut W) -> IoResult<()> {
+    let names: Vec<String> = matrix.types.iter().map(|&n| csv_field(&ginfo.graph.node_data(n).name[])).collect();
+    try!(writeln!(w, ",{}", names.connect(",")));
+    for (i, row) in matrix.cells.iter().enumerate() {
+        let cells: Vec<String> = row.iter().map(|&c| if c > 0 { c.to_string() } else { String::new() }).collect();
+        try!(writeln!(w, "{},{}", names[i], cells.connect(",")));
+    }
+    Ok(())
+}
+
+/// Quote a CSV field if it needs to be, as the type arguments of generic
+/// types hold commas.
+fn csv_field(s: &str) -> String {
+    if s.contains_char(',') || s.contains_char('"') || s.contains_char('\n') {
+        format!("\"{}\"", s.replace("\"", "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn write_text<W: Writer>(ginfo: &GraphInfo, matrix: &Matrix, w: &mut W) -> IoResult<()> {
+    let n = matrix.types.len();
+    let number_width = n.to_string().len();
+    let widest = matrix.cells.iter().flat_map(|row| row.iter()).map(|c| c.to_string().len()).max().unwrap_or(1);
+    let cell_width = if widest > number_width { widest } else { number_width };
+    let names: Vec<&str> = matrix.types.iter().map(|&n| &ginfo.graph.node_data(n).name[]).collect();
+    let name_width = names.iter().map(|name| name.chars().count()).max().unwrap_or(0);
+
+    let mut header = pad("", number_width + 1 + name_width, false);
+    for i in range(0, n) {
+        header.push(' ');
+        header.push_str(&pad(&(i + 1).to_string()[], cell_width, true)[]);
+    }
+    try!(writeln!(w, "{}", header.trim_right()));
+
+    for (i, row) in matrix.cells.iter().enumerate() {
+        let mut line = format!("{} {}", pad(&(i + 1).to_string()[], number_width, true),
+                               pad(names[i], name_width, false));
+        for (j, &count) in row.iter().enumerate() {
+            let cell = if i == j {
+                "-".to_string()
+            } else if count > 0 {
+                count.to_string()
+            } else if matrix.in_cycle(i, j) {
+                ".".to_string()
+            } else {
+                String::new()
+            };
+            line.push(' ');
+            line.push_str(&pad(&cell[], cell_width, true)[]);
+        }
+        try!(writeln!(w, "{}", line.trim_right()));
+    }
+    Ok(())
+}
+
+/// Pad a string with spaces to the given width, on the left if it's to be
+/// aligned right.
+fn pad(s: &str, width: usize, right: bool) -> String {
+    let mut spaces = String::new();
+    for _ in range(s.chars().count(), width) {
+        spaces.push(' ');
+    }
+    if right { spaces + s } else { s.to_string() + &spaces[] }
+}
+
+fn write_html<W: Writer>(ginfo: &GraphInfo, matrix: &Matrix, w: &mut W) -> IoResult<()> {
+    let name = escape(&ginfo.name[]);
+    try!(writeln!(w, "<!DOCTYPE html>"));
+    try!(writeln!(w, "<html>"));
+    try!(writeln!(w, "<head>"));
+    try!(writeln!(w, "<meta charset=\"utf-8\">"));
+    try!(writeln!(w, "<title>{}</title>", name));
+    try!(writeln!(w, "<style>"));
+    try!(writeln!(w, "  table {{ border-collapse: collapse; font: 12px sans-serif; }}"));
+    try!(writeln!(w, "  th, td {{ border: 1px solid #ddd; padding: 2px 4px; text-align: center; }}"));
+    try!(writeln!(w, "  th.name {{ text-align: left; white-space: nowrap; }}"));
+    try!(writeln!(w, "  td.self {{ background: #666; }}"));
+    try!(writeln!(w, "  td.cycle {{ background: #f8c8c8; }}"));
+    try!(writeln!(w, "</style>"));
+    try!(writeln!(w, "</head>"));
+    try!(writeln!(w, "<body>"));
+    try!(writeln!(w, "<table>"));
+
+    let mut header = "<tr><th></th><th></th>".to_string();
+    for i in range(0, matrix.types.len()) {
+        header.push_str(&format!("<th>{}</th>", i + 1)[]);
+    }
+    try!(writeln!(w, "{}</tr>", header));
+
+    for (i, row) in matrix.cells.iter().enumerate() {
+        let row_name = escape(&ginfo.graph.node_data(matrix.types[i]).name[]);
+        let mut line = format!("<tr><th>{}</th><th class=\"name\">{}</th>", i + 1, row_name);
+        for (j, &count) in row.iter().enumerate() {
+            let column_name = escape(&ginfo.graph.node_data(matrix.types[j]).name[]);
+            let class = if i == j { " class=\"self\"" } else if matrix.in_cycle(i, j) { " class=\"cycle\"" } else { "" };
+            let text = if count > 0 && i != j { count.to_string() } else { String::new() };
+            line.push_str(&format!("<td{} title=\"{} uses {}\">{}</td>", class, row_name, column_name, text)[]);
+        }
+        try!(writeln!(w, "{}</tr>", line));
+    }
+
+    try!(writeln!(w, "</table>"));
+    try!(writeln!(w, "</body>"));
+    writeln!(w, "</html>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Order::{Namespace, Topological};
+    use super::Style::{Csv, Text, Html};
+    use graph::EdgeIndex;
+    use backend::GraphInfo;
+    use test_support::test_graph;
+    use backend::EdgeKind::Field;
+
+    // A, B and D form a cycle, and B uses C twice.
+    fn create_graph() -> GraphInfo {
+        let mut ginfo = test_graph(&["Y.A", "Y.B", "X.C", "Y.D<K, V>"],
+                                   &[(0, 1, Field), (1, 3, Field), (3, 0, Field), (1, 2, Field)]);
+        ginfo.graph.mut_edge_data(EdgeIndex(3)).weight = 2;
+        ginfo
+    }
+
+    fn rendered(order: Order, style: Style) -> String {
+        let mut out = vec![];
+        render(&create_graph(), order, style, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(rendered(Topological, Text), "            1 2 3 4
+1 X.C       -
+2 Y.A         - 1 .
+3 Y.B       2 . - 1
+4 Y.D<K, V>   1 . -
+");
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(rendered(Namespace, Csv), r#",X.C,Y.A,Y.B,"Y.D<K, V>"
+X.C,,,,
+Y.A,,,1,
+Y.B,2,,,1
+"Y.D<K, V>",,1,,
+"#);
+    }
+
+    #[test]
+    fn html() {
+        let mut ginfo = create_graph();
+        ginfo.name = "Mine & Yours".to_string();
+        let mut out = vec![];
+        render(&ginfo, Topological, Html, &mut out);
+        let html = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = html.lines().collect();
+
+        assert!(lines.contains(&"<title>Mine &amp; Yours</title>"));
+        assert!(lines.contains(&"<tr><th></th><th></th><th>1</th><th>2</th><th>3</th><th>4</th></tr>"));
+        assert!(lines.contains(&concat!(
+            "<tr><th>4</th><th class=\"name\">Y.D&lt;K, V&gt;</th>",
+            "<td title=\"Y.D&lt;K, V&gt; uses X.C\"></td>",
+            "<td class=\"cycle\" title=\"Y.D&lt;K, V&gt; uses Y.A\">1</td>",
+            "<td class=\"cycle\" title=\"Y.D&lt;K, V&gt; uses Y.B\"></td>",
+            "<td class=\"self\" title=\"Y.D&lt;K, V&gt; uses Y.D&lt;K, V&gt;\"></td></tr>")));
+        assert_eq!(lines[lines.len() - 1], "</html>");
+    }
+}
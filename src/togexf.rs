@@ -0,0 +1,176 @@
+//! Writing the graph as GEXF, Gephi's own format. The properties of the types
+//! and edges are declared as typed attributes, so Gephi can filter and colour
+//! by them.
+
+use std::io::IoResult;
+use backend::{GraphInfo, Location};
+use xml::escape;
+
+/// The attributes declared for nodes and edges, numbered by position: name
+/// and type.
+static NODE_ATTRIBUTES: &'static [(&'static str, &'static str)] = &[
+    ("kind", "string"),
+    ("namespace", "string"),
+    ("project", "string"),
+    ("file", "string"),
+    ("line", "integer"),
+    ("markers", "string"),
+];
+static EDGE_ATTRIBUTES: &'static [(&'static str, &'static str)] = &[
+    ("kind", "string"),
+    ("implied", "boolean"),
+    ("file", "string"),
+    ("line", "integer"),
+];
+
+/// Render the graph to the file as a GEXF document.
+pub fn render<W: Writer>(ginfo: &GraphInfo, output: &mut W) {
+    write_graph(ginfo, output).unwrap()
+}
+
+fn write_graph<W: Writer>(ginfo: &GraphInfo, w: &mut W) -> IoResult<()> {
+    let g = &ginfo.graph;
+    try!(writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+    try!(writeln!(w, r#"<gexf xmlns="http://www.gexf.net/1.2draft" version="1.2">"#));
+    try!(writeln!(w, "  <meta>"));
+    try!(writeln!(w, "    <description>{}</description>", escape(&ginfo.name[])));
+    try!(writeln!(w, "  </meta>"));
+    try!(writeln!(w, r#"  <graph mode="static" defaultedgetype="directed">"#));
+    try!(write_attributes("node", NODE_ATTRIBUTES, w));
+    try!(write_attributes("edge", EDGE_ATTRIBUTES, w));
+
+    try!(writeln!(w, "    <nodes>"));
+    for (i, node) in g.all_nodes().iter().enumerate() {
+        let node = &node.data;
+        let mut values = vec![(0, node.kind.name().to_string()), (1, node.namespace.clone())];
+        if let Some(ref project) = node.project {
+            values.push((2, project.clone()));
+        }
+        if !node.locations.is_empty() {
+            values.push_all(&location_values(3, &node.locations[0])[]);
+        }
+        if !node.markers.is_empty() {
+            values.push((5, node.markers.connect(", ")));
+        }
+        try!(writeln!(w, r#"      <node id="{}" label="{}">"#, i, escape(&node.name[])));
+        try!(write_values(&values[], w));
+        try!(writeln!(w, "      </node>"));
+    }
+    try!(writeln!(w, "    </nodes>"));
+
+    try!(writeln!(w, "    <edges>"));
+    for (i, edge) in g.all_edges().iter().enumerate() {
+        let mut values = vec![(0, edge.data.kind.name().to_string()), (1, edge.data.implied.to_string())];
+        values.push_all(&location_values(2, &edge.data.location)[]);
+        try!(writeln!(w, r#"      <edge id="{}" source="{}" target="{}" weight="{}">"#,
+                      i, edge.source().node_id(), edge.target().node_id(), edge.data.weight));
+        try!(write_values(&values[], w));
+        try!(writeln!(w, "      </edge>"));
+    }
+    try!(writeln!(w, "    </edges>"));
+
+    try!(writeln!(w, "  </graph>"));
+    writeln!(w, "</gexf>")
+}
+
+fn write_attributes<W: Writer>(class: &str, attributes: &[(&str, &str)], w: &mut W) -> IoResult<()> {
+    try!(writeln!(w, r#"    <attributes class="{}">"#, class));
+    for (i, &(title, ty)) in attributes.iter().enumerate() {
+        try!(writeln!(w, r#"      <attribute id="{}" title="{}" type="{}"/>"#, i, title, ty));
+    }
+    writeln!(w, "    </attributes>")
+}
+
+/// The values of the file and line attributes, numbered from `first`.
+fn location_values(first: usize, location: &Location) -> Vec<(usize, String)> {
+    vec![(first, location.file.display().to_string()), (first + 1, location.span.line.to_string())]
+}
+
+fn write_values<W: Writer>(values: &[(usize, String)], w: &mut W) -> IoResult<()> {
+    try!(writeln!(w, "        <attvalues>"));
+    for &(attribute, ref value) in values.iter() {
+        try!(writeln!(w, r#"          <attvalue for="{}" value="{}"/>"#, attribute, escape(&value[])));
+    }
+    writeln!(w, "        </attvalues>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::{NodeIndex, EdgeIndex};
+    use test_support::test_graph;
+    use backend::TypeKind::Interface;
+    use backend::EdgeKind::Implementation;
+
+    #[test]
+    fn gexf() {
+        let mut ginfo = test_graph(&["N.A<T>", "IB"], &[(0, 1, Implementation)]);
+        let location = ginfo.graph.edge_data(EdgeIndex(0)).location.clone();
+        {
+            let node = ginfo.graph.mut_node_data(NodeIndex(0));
+            node.project = Some("App".to_string());
+            node.locations.push(location);
+            node.markers.push("Log".to_string());
+        }
+        ginfo.graph.mut_node_data(NodeIndex(1)).kind = Interface;
+        {
+            let edge = ginfo.graph.mut_edge_data(EdgeIndex(0));
+            edge.weight = 2;
+            edge.implied = true;
+        }
+
+        let mut out = vec![];
+        render(&ginfo, &mut out);
+        assert_eq!(String::from_utf8(out).unwrap(), r#"<?xml version="1.0" encoding="UTF-8"?>
+<gexf xmlns="http://www.gexf.net/1.2draft" version="1.2">
+  <meta>
+    <description>test</description>
+  </meta>
+  <graph mode="static" defaultedgetype="directed">
+    <attributes class="node">
+      <attribute id="0" title="kind" type="string"/>
+      <attribute id="1" title="namespace" type="string"/>
+      <attribute id="2" title="project" type="string"/>
+      <attribute id="3" title="file" type="string"/>
+      <attribute id="4" title="line" type="integer"/>
+      <attribute id="5" title="markers" type="string"/>
+    </attributes>
+    <attributes class="edge">
+      <attribute id="0" title="kind" type="string"/>
+      <attribute id="1" title="implied" type="boolean"/>
+      <attribute id="2" title="file" type="string"/>
+      <attribute id="3" title="line" type="integer"/>
+    </attributes>
+    <nodes>
+      <node id="0" label="N.A&lt;T&gt;">
+        <attvalues>
+          <attvalue for="0" value="class"/>
+          <attvalue for="1" value="N"/>
+          <attvalue for="2" value="App"/>
+          <attvalue for="3" value="0.cs"/>
+          <attvalue for="4" value="1"/>
+          <attvalue for="5" value="Log"/>
+        </attvalues>
+      </node>
+      <node id="1" label="IB">
+        <attvalues>
+          <attvalue for="0" value="interface"/>
+          <attvalue for="1" value=""/>
+        </attvalues>
+      </node>
+    </nodes>
+    <edges>
+      <edge id="0" source="0" target="1" weight="2">
+        <attvalues>
+          <attvalue for="0" value="implementation"/>
+          <attvalue for="1" value="true"/>
+          <attvalue for="2" value="0.cs"/>
+          <attvalue for="3" value="1"/>
+        </attvalues>
+      </edge>
+    </edges>
+  </graph>
+</gexf>
+"#);
+    }
+}
@@ -0,0 +1,161 @@
+//! Writing the graph as GraphML, which yEd and Gephi open directly. Every
+//! property of the types and edges is declared as a typed key, so the tools
+//! can filter and colour by it. Each type is also given a yEd label with its
+//! name, as yEd doesn't show the `name` key by itself.
+
+use std::io::IoResult;
+use backend::{GraphInfo, Location};
+use xml::escape;
+
+/// The keys declared for nodes and edges: id, name and type.
+static NODE_KEYS: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("name", "name", "string"),
+    ("kind", "kind", "string"),
+    ("namespace", "namespace", "string"),
+    ("project", "project", "string"),
+    ("file", "file", "string"),
+    ("line", "line", "int"),
+    ("markers", "markers", "string"),
+];
+static EDGE_KEYS: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("edge_kind", "kind", "string"),
+    ("weight", "weight", "int"),
+    ("implied", "implied", "boolean"),
+    ("edge_file", "file", "string"),
+    ("edge_line", "line", "int"),
+];
+
+/// Render the graph to the file as a GraphML document.
+pub fn render<W: Writer>(ginfo: &GraphInfo, output: &mut W) {
+    write_graph(ginfo, output).unwrap()
+}
+
+fn write_graph<W: Writer>(ginfo: &GraphInfo, w: &mut W) -> IoResult<()> {
+    let g = &ginfo.graph;
+    try!(writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+    try!(writeln!(w, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns" xmlns:y="http://www.yworks.com/xml/graphml">"#));
+    for &(id, name, ty) in NODE_KEYS.iter() {
+        try!(writeln!(w, r#"  <key id="{}" for="node" attr.name="{}" attr.type="{}"/>"#, id, name, ty));
+    }
+    for &(id, name, ty) in EDGE_KEYS.iter() {
+        try!(writeln!(w, r#"  <key id="{}" for="edge" attr.name="{}" attr.type="{}"/>"#, id, name, ty));
+    }
+    try!(writeln!(w, r#"  <key id="graphics" for="node" yfiles.type="nodegraphics"/>"#));
+    try!(writeln!(w, r#"  <key id="legend" for="graph" attr.name="legend" attr.type="string"/>"#));
+    try!(writeln!(w, r#"  <graph id="{}" edgedefault="directed">"#, escape(&ginfo.name[])));
+
+    if !ginfo.legend.is_empty() {
+        let lines: Vec<String> = ginfo.legend.iter().map(|&(ref key, ref name)| format!("{}: {}", key, name)).collect();
+        try!(writeln!(w, r#"    <data key="legend">{}</data>"#, escape(&lines.connect("\n")[])));
+    }
+
+    for (i, node) in g.all_nodes().iter().enumerate() {
+        let node = &node.data;
+        let mut data = vec![("name", node.name.clone()), ("kind", node.kind.name().to_string()),
+                            ("namespace", node.namespace.clone())];
+        if let Some(ref project) = node.project {
+            data.push(("project", project.clone()));
+        }
+        if !node.locations.is_empty() {
+            data.push_all(&location_data("file", "line", &node.locations[0])[]);
+        }
+        if !node.markers.is_empty() {
+            data.push(("markers", node.markers.connect(", ")));
+        }
+        try!(writeln!(w, r#"    <node id="n{}">"#, i));
+        try!(write_data(&data[], w));
+        try!(writeln!(w, r#"      <data key="graphics"><y:ShapeNode><y:NodeLabel>{}</y:NodeLabel></y:ShapeNode></data>"#,
+                      escape(&node.name[])));
+        try!(writeln!(w, "    </node>"));
+    }
+
+    for (i, edge) in g.all_edges().iter().enumerate() {
+        let mut data = vec![("edge_kind", edge.data.kind.name().to_string()), ("weight", edge.data.weight.to_string()),
+                            ("implied", edge.data.implied.to_string())];
+        data.push_all(&location_data("edge_file", "edge_line", &edge.data.location)[]);
+        try!(writeln!(w, r#"    <edge id="e{}" source="n{}" target="n{}">"#,
+                      i, edge.source().node_id(), edge.target().node_id()));
+        try!(write_data(&data[], w));
+        try!(writeln!(w, "    </edge>"));
+    }
+
+    try!(writeln!(w, "  </graph>"));
+    writeln!(w, "</graphml>")
+}
+
+fn location_data(file: &'static str, line: &'static str, location: &Location) -> Vec<(&'static str, String)> {
+    vec![(file, location.file.display().to_string()), (line, location.span.line.to_string())]
+}
+
+fn write_data<W: Writer>(data: &[(&'static str, String)], w: &mut W) -> IoResult<()> {
+    for &(key, ref value) in data.iter() {
+        try!(writeln!(w, r#"      <data key="{}">{}</data>"#, key, escape(&value[])));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::{NodeIndex, EdgeIndex};
+    use test_support::test_graph;
+    use backend::TypeKind::Interface;
+    use backend::EdgeKind::Implementation;
+
+    #[test]
+    fn graphml() {
+        let mut ginfo = test_graph(&["N.A<T>", "IB"], &[(0, 1, Implementation)]);
+        let location = ginfo.graph.edge_data(EdgeIndex(0)).location.clone();
+        {
+            let node = ginfo.graph.mut_node_data(NodeIndex(0));
+            node.project = Some("App".to_string());
+            node.locations.push(location);
+        }
+        ginfo.graph.mut_node_data(NodeIndex(1)).kind = Interface;
+
+        let mut out = vec![];
+        render(&ginfo, &mut out);
+        assert_eq!(String::from_utf8(out).unwrap(), r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns" xmlns:y="http://www.yworks.com/xml/graphml">
+  <key id="name" for="node" attr.name="name" attr.type="string"/>
+  <key id="kind" for="node" attr.name="kind" attr.type="string"/>
+  <key id="namespace" for="node" attr.name="namespace" attr.type="string"/>
+  <key id="project" for="node" attr.name="project" attr.type="string"/>
+  <key id="file" for="node" attr.name="file" attr.type="string"/>
+  <key id="line" for="node" attr.name="line" attr.type="int"/>
+  <key id="markers" for="node" attr.name="markers" attr.type="string"/>
+  <key id="edge_kind" for="edge" attr.name="kind" attr.type="string"/>
+  <key id="weight" for="edge" attr.name="weight" attr.type="int"/>
+  <key id="implied" for="edge" attr.name="implied" attr.type="boolean"/>
+  <key id="edge_file" for="edge" attr.name="file" attr.type="string"/>
+  <key id="edge_line" for="edge" attr.name="line" attr.type="int"/>
+  <key id="graphics" for="node" yfiles.type="nodegraphics"/>
+  <key id="legend" for="graph" attr.name="legend" attr.type="string"/>
+  <graph id="test" edgedefault="directed">
+    <node id="n0">
+      <data key="name">N.A&lt;T&gt;</data>
+      <data key="kind">class</data>
+      <data key="namespace">N</data>
+      <data key="project">App</data>
+      <data key="file">0.cs</data>
+      <data key="line">1</data>
+      <data key="graphics"><y:ShapeNode><y:NodeLabel>N.A&lt;T&gt;</y:NodeLabel></y:ShapeNode></data>
+    </node>
+    <node id="n1">
+      <data key="name">IB</data>
+      <data key="kind">interface</data>
+      <data key="namespace"></data>
+      <data key="graphics"><y:ShapeNode><y:NodeLabel>IB</y:NodeLabel></y:ShapeNode></data>
+    </node>
+    <edge id="e0" source="n0" target="n1">
+      <data key="edge_kind">implementation</data>
+      <data key="weight">1</data>
+      <data key="implied">false</data>
+      <data key="edge_file">0.cs</data>
+      <data key="edge_line">1</data>
+    </edge>
+  </graph>
+</graphml>
+"#);
+    }
+}
@@ -0,0 +1,47 @@
+//! Writing the graph as a single HTML page holding the graph and a viewer for
+//! it, which works offline. The graph is laid out by a force simulation, and
+//! can be panned, zoomed, searched and clicked on for details.
+
+use std::io::IoResult;
+use backend::GraphInfo;
+use tojson;
+use xml::escape;
+
+/// The page, with `__NAME__` standing for the name of the graph and
+/// `__GRAPH__` for the graph as JSON.
+static VIEWER: &'static str = include_str!("viewer.html");
+
+/// Render the graph to the file as an HTML page.
+pub fn render<W: Writer>(ginfo: &GraphInfo, output: &mut W) {
+    write_graph(ginfo, output).unwrap()
+}
+
+fn write_graph<W: Writer>(ginfo: &GraphInfo, w: &mut W) -> IoResult<()> {
+    let split = VIEWER.find_str("__GRAPH__").unwrap();
+    let (head, tail) = (&VIEWER[..split], &VIEWER[split + "__GRAPH__".len()..]);
+
+    // The JSON goes inside a script element, which the first `</` would end.
+    let graph = tojson::encode(ginfo).replace("</", "<\\/");
+
+    try!(w.write_str(&head.replace("__NAME__", &escape(&ginfo.name[])[])[]));
+    try!(w.write_str(&graph[]));
+    w.write_str(tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_support::test_graph;
+
+    #[test]
+    fn html() {
+        let ginfo = test_graph(&["A</script>"], &[]);
+
+        let mut out = vec![];
+        render(&ginfo, &mut out);
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("<title>test</title>"));
+        assert!(html.contains(r#""name":"A<\/script>""#));
+        assert!(!html.contains("__GRAPH__") && !html.contains("__NAME__"));
+    }
+}
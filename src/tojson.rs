@@ -0,0 +1,155 @@
+//! Writing the graph as JSON, for tools of one's own to read.
+//!
+//! Each graph is written as one document on a line of its own:
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "name": "reference_graph",
+//!   "nodes": [{"id": 0, "name": "App.Main", "kind": "class", "namespace": "App", "project": "App",
+//!              "locations": [{"file": "src/Main.cs", "line": 3, "column": 5, "start": 40, "end": 62}],
+//!              "markers": []}],
+//!   "edges": [{"source": 0, "target": 1, "kind": "field", "weight": 1, "implied": false,
+//!              "location": {"file": "src/Main.cs", "line": 5, "column": 9, "start": 80, "end": 84}}],
+//!   "legend": [{"key": "H1", "name": "App.Log"}]
+//! }
+//! ```
+//!
+//! Nodes are numbered by their position in `nodes`, which edges refer to.
+//! `project` is null when the project isn't known. Lines and columns count
+//! from 1, and `start` and `end` are byte offsets. The version goes up
+//! whenever a change could break existing readers.
+
+use std::io::IoResult;
+use rustc_serialize::json;
+use backend::{GraphInfo, Location};
+
+/// The version of the layout of the documents written.
+pub const SCHEMA_VERSION: usize = 1;
+
+#[derive(RustcEncodable)]
+struct Document {
+    version: usize,
+    name: String,
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    legend: Vec<LegendEntry>,
+}
+
+#[derive(RustcEncodable)]
+struct Node {
+    id: usize,
+    name: String,
+    kind: String,
+    namespace: String,
+    project: Option<String>,
+    locations: Vec<Place>,
+    markers: Vec<String>,
+}
+
+#[derive(RustcEncodable)]
+struct Edge {
+    source: usize,
+    target: usize,
+    kind: String,
+    weight: usize,
+    implied: bool,
+    location: Place,
+}
+
+#[derive(RustcEncodable)]
+struct Place {
+    file: String,
+    line: usize,
+    column: usize,
+    start: usize,
+    end: usize,
+}
+
+#[derive(RustcEncodable)]
+struct LegendEntry {
+    key: String,
+    name: String,
+}
+
+/// Render the graph to the file as a JSON document.
+pub fn render<W: Writer>(ginfo: &GraphInfo, output: &mut W) {
+    write_graph(ginfo, output).unwrap()
+}
+
+fn write_graph<W: Writer>(ginfo: &GraphInfo, w: &mut W) -> IoResult<()> {
+    writeln!(w, "{}", encode(ginfo))
+}
+
+/// The graph as a JSON document on a single line.
+pub fn encode(ginfo: &GraphInfo) -> String {
+    let g = &ginfo.graph;
+    let document = Document {
+        version: SCHEMA_VERSION,
+        name: ginfo.name.clone(),
+        nodes: g.all_nodes().iter().enumerate().map(|(i, node)| Node {
+            id: i,
+            name: node.data.name.clone(),
+            kind: node.data.kind.name().to_string(),
+            namespace: node.data.namespace.clone(),
+            project: node.data.project.clone(),
+            locations: node.data.locations.iter().map(place).collect(),
+            markers: node.data.markers.clone(),
+        }).collect(),
+        edges: g.all_edges().iter().map(|edge| Edge {
+            source: edge.source().node_id(),
+            target: edge.target().node_id(),
+            kind: edge.data.kind.name().to_string(),
+            weight: edge.data.weight,
+            implied: edge.data.implied,
+            location: place(&edge.data.location),
+        }).collect(),
+        legend: ginfo.legend.iter().map(|&(ref key, ref name)| LegendEntry { key: key.clone(), name: name.clone() })
+            .collect(),
+    };
+    json::encode(&document)
+}
+
+fn place(location: &Location) -> Place {
+    Place {
+        file: location.file.display().to_string(),
+        line: location.span.line,
+        column: location.span.column,
+        start: location.span.start,
+        end: location.span.end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::{NodeIndex, EdgeIndex};
+    use test_support::test_graph;
+    use backend::TypeKind::Interface;
+    use backend::EdgeKind::Implementation;
+
+    #[test]
+    fn json() {
+        let mut ginfo = test_graph(&["N.A", "I\"B\""], &[(0, 1, Implementation)]);
+        let location = ginfo.graph.edge_data(EdgeIndex(0)).location.clone();
+        {
+            let node = ginfo.graph.mut_node_data(NodeIndex(0));
+            node.project = Some("App".to_string());
+            node.locations.push(location);
+            node.markers.push("H1".to_string());
+        }
+        ginfo.graph.mut_node_data(NodeIndex(1)).kind = Interface;
+        ginfo.graph.mut_edge_data(EdgeIndex(0)).weight = 2;
+        ginfo.legend.push(("H1".to_string(), "Log".to_string()));
+
+        let mut out = vec![];
+        render(&ginfo, &mut out);
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}{}{}{}{}{}\n",
+            r#"{"version":1,"name":"test","#,
+            r#""nodes":[{"id":0,"name":"N.A","kind":"class","namespace":"N","project":"App","#,
+            r#""locations":[{"file":"0.cs","line":1,"column":1,"start":0,"end":0}],"markers":["H1"]},"#,
+            r#"{"id":1,"name":"I\"B\"","kind":"interface","namespace":"","project":null,"locations":[],"markers":[]}],"#,
+            r#""edges":[{"source":0,"target":1,"kind":"implementation","weight":2,"implied":false,"location":{"file":"0.cs","line":1,"column":1,"start":0,"end":0}}],"#,
+            r#""legend":[{"key":"H1","name":"Log"}]}"#));
+    }
+}
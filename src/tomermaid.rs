@@ -0,0 +1,104 @@
+//! Writing the graph as a Mermaid class diagram, for Markdown documents and
+//! wikis that render Mermaid.
+
+use std::io::IoResult;
+use backend::{GraphInfo, TypeKind};
+use backend::TypeKind::{Class, Struct, Interface, Enum, Record, RecordStruct, Delegate, Namespace, Cycle};
+use uml::{Relationship, relationship, edge_label};
+use uml::Relationship::{Generalization, Realization, Nesting, Association, Dependency};
+
+/// Render the graph to the file as a Mermaid class diagram.
+pub fn render<W: Writer>(ginfo: &GraphInfo, output: &mut W) {
+    write_graph(ginfo, output).unwrap()
+}
+
+fn write_graph<W: Writer>(ginfo: &GraphInfo, w: &mut W) -> IoResult<()> {
+    let g = &ginfo.graph;
+    try!(writeln!(w, "classDiagram"));
+
+    // Names may hold characters Mermaid doesn't allow in ids, such as the
+    // angle brackets of generics, so nodes are identified by index and the
+    // name goes in the label instead.
+    for (i, node) in g.all_nodes().iter().enumerate() {
+        let node = &node.data;
+        try!(writeln!(w, "    class N{}[\"{}\"]", i, quote(&node.name[])));
+        if let Some(annotation) = annotation(node.kind) {
+            try!(writeln!(w, "    <<{}>> N{}", annotation, i));
+        }
+        if !node.markers.is_empty() {
+            try!(writeln!(w, "    note for N{} \"uses {}\"", i, quote(&node.markers.connect(", ")[])));
+        }
+    }
+
+    for edge in g.all_edges().iter() {
+        try!(writeln!(w, "    N{} {} N{}{}", edge.source().node_id(), arrow(relationship(&edge.data)),
+                      edge.target().node_id(), edge_label(&edge.data)));
+    }
+    Ok(())
+}
+
+/// The annotation shown above the name for kinds other than classes.
+fn annotation(kind: TypeKind) -> Option<&'static str> {
+    match kind {
+        Class => None,
+        Struct => Some("struct"),
+        Interface => Some("interface"),
+        Enum => Some("enumeration"),
+        Record => Some("record"),
+        RecordStruct => Some("record struct"),
+        Delegate => Some("delegate"),
+        Namespace => Some("namespace"),
+        Cycle => Some("cycle"),
+    }
+}
+
+/// The arrow for each relationship. Mermaid has no nesting, so nested types
+/// are drawn as composition.
+fn arrow(relationship: Relationship) -> &'static str {
+    match relationship {
+        Generalization => "--|>",
+        Realization => "..|>",
+        Nesting => "*--",
+        Association => "-->",
+        Dependency => "..>",
+    }
+}
+
+/// Make a string safe to put inside double quotes.
+fn quote(s: &str) -> String {
+    s.replace("\"", "#quot;").replace("\n", ", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::{NodeIndex, EdgeIndex};
+    use test_support::test_graph;
+    use backend::TypeKind::Interface;
+    use backend::EdgeKind::{Inheritance, Implementation, Field, Parameter};
+
+    #[test]
+    fn mermaid() {
+        let mut ginfo = test_graph(&["Base", "IThing", "N.Thing<T>", "Part"],
+                                   &[(2, 0, Inheritance), (2, 1, Implementation), (2, 3, Field), (3, 1, Parameter)]);
+        ginfo.graph.mut_node_data(NodeIndex(1)).kind = Interface;
+        ginfo.graph.mut_node_data(NodeIndex(3)).markers.push("Log".to_string());
+        ginfo.graph.mut_edge_data(EdgeIndex(3)).weight = 3;
+
+        let mut out = vec![];
+        render(&ginfo, &mut out);
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+classDiagram
+    class N0[\"Base\"]
+    class N1[\"IThing\"]
+    <<interface>> N1
+    class N2[\"N.Thing<T>\"]
+    class N3[\"Part\"]
+    note for N3 \"uses Log\"
+    N2 --|> N0
+    N2 ..|> N1
+    N2 --> N3
+    N3 ..> N1 : parameter x3
+");
+    }
+}
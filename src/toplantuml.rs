@@ -0,0 +1,108 @@
+//! Writing the graph as a PlantUML class diagram, for documents and wikis that
+//! render PlantUML.
+
+use std::io::IoResult;
+use backend::{GraphInfo, TypeKind};
+use backend::TypeKind::{Class, Struct, Interface, Enum, Record, RecordStruct, Delegate, Namespace, Cycle};
+use uml::{Relationship, relationship, edge_label};
+use uml::Relationship::{Generalization, Realization, Nesting, Association, Dependency};
+
+/// Render the graph to the file as a PlantUML class diagram.
+pub fn render<W: Writer>(ginfo: &GraphInfo, output: &mut W) {
+    write_graph(ginfo, output).unwrap()
+}
+
+fn write_graph<W: Writer>(ginfo: &GraphInfo, w: &mut W) -> IoResult<()> {
+    let g = &ginfo.graph;
+    try!(writeln!(w, "@startuml"));
+
+    // Nodes are identified by index, with the name given as the display name,
+    // so that generics and nested types come out as written.
+    for (i, node) in g.all_nodes().iter().enumerate() {
+        let node = &node.data;
+        let (keyword, stereotype) = declaration(node.kind);
+        let stereotype = match stereotype {
+            Some(stereotype) => format!(" <<{}>>", stereotype),
+            None => String::new(),
+        };
+        try!(writeln!(w, "{} \"{}\" as N{}{}", keyword, quote(&node.name[]), i, stereotype));
+        if !node.markers.is_empty() {
+            try!(writeln!(w, "note right of N{} : uses {}", i, node.markers.connect(", ")));
+        }
+    }
+
+    for edge in g.all_edges().iter() {
+        try!(writeln!(w, "N{} {} N{}{}", edge.source().node_id(), arrow(relationship(&edge.data)),
+                      edge.target().node_id(), edge_label(&edge.data)));
+    }
+
+    writeln!(w, "@enduml")
+}
+
+/// The keyword each kind of type is declared with, and a stereotype telling
+/// apart those that share one.
+fn declaration(kind: TypeKind) -> (&'static str, Option<&'static str>) {
+    match kind {
+        Class => ("class", None),
+        Struct => ("struct", None),
+        Interface => ("interface", None),
+        Enum => ("enum", None),
+        Record => ("class", Some("record")),
+        RecordStruct => ("struct", Some("record")),
+        Delegate => ("class", Some("delegate")),
+        Namespace => ("class", Some("namespace")),
+        Cycle => ("class", Some("cycle")),
+    }
+}
+
+/// The arrow for each relationship.
+fn arrow(relationship: Relationship) -> &'static str {
+    match relationship {
+        Generalization => "--|>",
+        Realization => "..|>",
+        Nesting => "+--",
+        Association => "-->",
+        Dependency => "..>",
+    }
+}
+
+/// Make a string safe to put inside double quotes. Newlines become line
+/// breaks in the display name.
+fn quote(s: &str) -> String {
+    s.replace("\"", "'").replace("\n", "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::{NodeIndex, EdgeIndex};
+    use test_support::test_graph;
+    use backend::TypeKind::{Interface, Record};
+    use backend::EdgeKind::{Inheritance, Implementation, Contains, Reference};
+
+    #[test]
+    fn plantuml() {
+        let mut ginfo = test_graph(&["Base", "IThing", "N.Thing<T>", "N.Thing<T>.Part"],
+                                   &[(2, 0, Inheritance), (2, 1, Implementation), (2, 3, Contains), (3, 1, Reference)]);
+        ginfo.graph.mut_node_data(NodeIndex(1)).kind = Interface;
+        ginfo.graph.mut_node_data(NodeIndex(2)).kind = Record;
+        ginfo.graph.mut_node_data(NodeIndex(3)).markers.push("Log".to_string());
+        ginfo.graph.mut_edge_data(EdgeIndex(3)).weight = 2;
+
+        let mut out = vec![];
+        render(&ginfo, &mut out);
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+@startuml
+class \"Base\" as N0
+interface \"IThing\" as N1
+class \"N.Thing<T>\" as N2 <<record>>
+class \"N.Thing<T>.Part\" as N3
+note right of N3 : uses Log
+N2 --|> N0
+N2 ..|> N1
+N2 +-- N3
+N3 ..> N1 : reference x2
+@enduml
+");
+    }
+}
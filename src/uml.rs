@@ -0,0 +1,43 @@
+//! Helpers shared by the UML class diagram outputs.
+
+use backend::EdgeInfo;
+use backend::EdgeKind::{Inheritance, Implementation, Contains, Constraint, Field, Parameter, Instantiation, StaticAccess,
+                        Reference};
+use self::Relationship::{Generalization, Realization, Nesting, Association, Dependency};
+
+/// The UML relationships edges are drawn as.
+#[derive(Clone, Copy, PartialEq, Show)]
+pub enum Relationship {
+    Generalization,
+    Realization,
+    Nesting,
+    Association,
+    Dependency,
+}
+
+/// The relationship an edge is drawn as: generalization and realization for
+/// inheritance and implementation, nesting for nested types, association for
+/// fields and dependency for the rest.
+pub fn relationship(edge: &EdgeInfo) -> Relationship {
+    match edge.kind {
+        Inheritance => Generalization,
+        Implementation => Realization,
+        Contains => Nesting,
+        Field => Association,
+        Constraint | Parameter | Instantiation | StaticAccess | Reference => Dependency,
+    }
+}
+
+/// The label written after the arrow of an edge, as in ` : parameter x3`, or
+/// nothing. Dependencies are labelled with their kind, as they all look the
+/// same, and edges standing for several with how many.
+pub fn edge_label(edge: &EdgeInfo) -> String {
+    let mut label = if relationship(edge) == Dependency { edge.kind.name().to_string() } else { String::new() };
+    if edge.weight > 1 {
+        if !label.is_empty() {
+            label.push(' ');
+        }
+        label.push_str(&format!("x{}", edge.weight)[]);
+    }
+    if label.is_empty() { label } else { format!(" : {}", label) }
+}
@@ -0,0 +1,6 @@
+//! Helpers shared by the XML based outputs.
+
+/// Escape a string for use in XML text or a quoted attribute value.
+pub fn escape(s: &str) -> String {
+    s.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;").replace("\"", "&quot;").replace("'", "&apos;")
+}